@@ -0,0 +1,300 @@
+use std::collections::BTreeSet;
+
+use surrealdb::{
+    sql::{
+        statements::{BeginStatement, CommitStatement, InsertStatement, UpdateStatement},
+        Cond, Data, Expression, Ident, Idiom, Object, Operator, Part, Statement, Table, Value,
+        Values,
+    },
+    Datastore, Session,
+};
+
+use super::{
+    convert::{take_path, FromValue, ToValue},
+    run_single_statement, run_statements,
+    select::FieldRef,
+    Error,
+};
+
+/// The most rows sent to the database at once. Bigger batches are split up, so one huge insert
+/// doesn't have to be parsed and held in memory all together.
+pub const CHUNK_SIZE: usize = 1000;
+
+/// `INSERT INTO person [...]`, one statement per [`CHUNK_SIZE`] rows.
+pub fn insert_statements<V: ToValue>(table: &str, rows: &[V]) -> Vec<Statement> {
+    rows.chunks(CHUNK_SIZE)
+        .map(|chunk| {
+            let values: Vec<Value> = chunk.iter().map(ToValue::to_value).collect();
+
+            Statement::Insert(InsertStatement {
+                into: Table::from(table),
+                data: Data::SingleExpression(Value::Array(values.into())),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Inserts all of `rows`, returning the records created with their ids, in the same order.
+pub async fn insert_many<V: ToValue, T: FromValue>(
+    ds: &Datastore,
+    sess: &Session,
+    table: &str,
+    rows: &[V],
+) -> Result<Vec<T>, Error> {
+    let mut inserted = Vec::with_capacity(rows.len());
+
+    for statement in insert_statements(table, rows) {
+        let result = run_single_statement(ds, sess, statement, None).await?;
+        inserted.extend(Vec::<T>::from_value(result)?);
+    }
+
+    Ok(inserted)
+}
+
+fn to_object<V: ToValue>(row: &V) -> Result<Object, Error> {
+    Object::from_value(row.to_value())
+}
+
+fn update_statement(what: Value, data: Data, cond: Option<Cond>) -> Statement {
+    Statement::Update(UpdateStatement {
+        what: Values(vec![what]),
+        data: Some(data),
+        cond,
+        ..Default::default()
+    })
+}
+
+/// `UPDATE person:bob CONTENT {...}` for each row, which creates the record if it doesn't exist
+/// yet. The rows need an `id`.
+pub fn upsert_statements<V: ToValue>(rows: &[V]) -> Result<Vec<Statement>, Error> {
+    rows.iter()
+        .map(|row| {
+            let mut obj = to_object(row)?;
+            let id = match take_path(&mut obj, &["id"]) {
+                Value::Thing(id) => id,
+                Value::None => return Err(Error::MissingExpectedKey("id".to_string())),
+                v => {
+                    return Err(Error::UnexpectedType(
+                        "Thing".to_string(),
+                        format!("{:?}", v),
+                    ))
+                }
+            };
+
+            Ok(update_statement(
+                Value::Thing(id),
+                Data::ContentExpression(Value::Object(obj)),
+                None,
+            ))
+        })
+        .collect()
+}
+
+/// Runs statements that each return a list of records, [`CHUNK_SIZE`] to a query, giving the
+/// first record each one returned.
+///
+/// Each chunk runs in its own transaction, so a failure leaves none of that chunk applied.
+async fn run_chunked<T: FromValue>(
+    ds: &Datastore,
+    sess: &Session,
+    statements: Vec<Statement>,
+) -> Result<Vec<Option<T>>, Error> {
+    let mut records = Vec::with_capacity(statements.len());
+
+    for chunk in statements.chunks(CHUNK_SIZE) {
+        let mut transaction = Vec::with_capacity(chunk.len() + 2);
+        transaction.push(Statement::Begin(BeginStatement));
+        transaction.extend_from_slice(chunk);
+        transaction.push(Statement::Commit(CommitStatement));
+
+        for result in run_statements(ds, sess, transaction, None).await {
+            let mut found = Vec::<T>::from_value(result?)?;
+            records.push(if found.is_empty() {
+                None
+            } else {
+                Some(found.remove(0))
+            });
+        }
+    }
+
+    Ok(records)
+}
+
+/// Creates or replaces each row by its `id`, returning the records as stored.
+pub async fn upsert_many<V: ToValue, T: FromValue>(
+    ds: &Datastore,
+    sess: &Session,
+    rows: &[V],
+) -> Result<Vec<T>, Error> {
+    let records: Vec<T> = run_chunked(ds, sess, upsert_statements(rows)?)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if records.len() != rows.len() {
+        return Err(Error::UnexpectedResultCount(rows.len(), records.len()));
+    }
+
+    Ok(records)
+}
+
+/// `username = 'b0b'`
+fn key_condition<K>(key: FieldRef<K>, value: Value) -> Cond {
+    let idiom = Idiom(
+        key.path()
+            .iter()
+            .map(|k| Part::Field(Ident(k.to_string())))
+            .collect(),
+    );
+
+    Cond(Value::Expression(Box::new(Expression {
+        l: Value::Idiom(idiom),
+        o: Operator::Equal,
+        r: value,
+    })))
+}
+
+/// The value of `key` in each row, checking that every row has one, and that no two rows share
+/// one. Two new rows with the same key would otherwise both be inserted, and the second would
+/// fail on the `UNIQUE` index.
+fn key_values<V: ToValue, K>(key: FieldRef<K>, rows: &[V]) -> Result<Vec<Value>, Error> {
+    let mut seen = BTreeSet::new();
+
+    rows.iter()
+        .map(|row| {
+            let value = take_path(&mut to_object(row)?, key.path());
+            if value.is_none() {
+                return Err(Error::MissingExpectedKey(key.path().join(".")));
+            }
+            if !seen.insert(value.to_string()) {
+                return Err(Error::DuplicateKey {
+                    field: key.path().join("."),
+                    value: value.to_string(),
+                });
+            }
+
+            Ok(value)
+        })
+        .collect()
+}
+
+/// `UPDATE person MERGE {...} WHERE username = ...` for each row, matching on `key`.
+pub fn merge_by_statements<V: ToValue, K>(
+    table: &str,
+    key: FieldRef<K>,
+    rows: &[V],
+) -> Result<Vec<Statement>, Error> {
+    rows.iter()
+        .zip(key_values(key, rows)?)
+        .map(|(row, value)| {
+            Ok(update_statement(
+                Value::Table(Table::from(table)),
+                Data::MergeExpression(row.to_value()),
+                Some(key_condition(key, value)),
+            ))
+        })
+        .collect()
+}
+
+/// Merges each row into the record with the same value of `key`, which should have a `UNIQUE`
+/// index, and inserts the rows that don't match any. Returns the records as stored, in the same
+/// order as `rows`. No two rows can have the same value of `key`.
+///
+/// The updates and inserts are run separately, so a row inserted by someone else in between
+/// makes the insert fail on the index rather than adding a duplicate.
+pub async fn upsert_many_by<V: ToValue, T: FromValue, K>(
+    ds: &Datastore,
+    sess: &Session,
+    table: &str,
+    key: FieldRef<K>,
+    rows: &[V],
+) -> Result<Vec<T>, Error> {
+    let merged: Vec<Option<T>> =
+        run_chunked(ds, sess, merge_by_statements(table, key, rows)?).await?;
+
+    let missing: Vec<Value> = rows
+        .iter()
+        .zip(&merged)
+        .filter(|(_, record)| record.is_none())
+        .map(|(row, _)| row.to_value())
+        .collect();
+    let inserted = insert_many::<_, T>(ds, sess, table, &missing).await?;
+    if inserted.len() != missing.len() {
+        return Err(Error::UnexpectedResultCount(missing.len(), inserted.len()));
+    }
+
+    let mut inserted = inserted.into_iter();
+    Ok(merged
+        .into_iter()
+        .filter_map(|record| record.or_else(|| inserted.next()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::{Object, Thing, Value};
+
+    use super::{insert_statements, merge_by_statements, upsert_statements, CHUNK_SIZE};
+    use crate::{select::FieldRef, Error};
+
+    const USERNAME: FieldRef<String> = FieldRef::new(&["username"]);
+
+    fn person(id: Option<&str>, username: &str) -> Value {
+        let mut obj = Object::default();
+        if let Some(id) = id {
+            obj.0
+                .insert("id".to_string(), Value::Thing(Thing::from(("person", id))));
+        }
+        obj.0.insert("username".to_string(), Value::from(username));
+
+        Value::Object(obj)
+    }
+
+    #[test]
+    fn test_insert_statements_are_chunked() {
+        let rows: Vec<Value> = (0..CHUNK_SIZE + 1).map(|_| person(None, "b0b")).collect();
+        let statements = insert_statements("person", &rows);
+
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0]
+            .to_string()
+            .starts_with("INSERT INTO person [{ username: 'b0b' }, "));
+        assert_eq!(
+            statements[1].to_string(),
+            "INSERT INTO person [{ username: 'b0b' }]"
+        );
+    }
+
+    #[test]
+    fn test_upsert_statements() -> Result<(), Error> {
+        let statements = upsert_statements(&[person(Some("bob"), "b0b")])?;
+
+        assert_eq!(
+            statements[0].to_string(),
+            "UPDATE person:bob CONTENT { username: 'b0b' }"
+        );
+        assert!(upsert_statements(&[person(None, "b0b")]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_by_statements() -> Result<(), Error> {
+        let statements = merge_by_statements("person", USERNAME, &[person(None, "b0b")])?;
+
+        assert_eq!(
+            statements[0].to_string(),
+            "UPDATE person MERGE { username: 'b0b' } WHERE username = 'b0b'"
+        );
+
+        let duplicated = [person(None, "b0b"), person(Some("bob"), "b0b")];
+        assert!(matches!(
+            merge_by_statements("person", USERNAME, &duplicated),
+            Err(Error::DuplicateKey { .. })
+        ));
+
+        Ok(())
+    }
+}
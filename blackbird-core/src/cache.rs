@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use surrealdb::sql::parse;
+
+use super::{
+    schema::{definitions, schemas_from_definitions, TableSchema},
+    Context, Error,
+};
+
+/// The version of what gets written to the cache, which needs bumping whenever `TableSchema`
+/// writes out something it didn't before, like indexes. Otherwise a cache from an older build
+/// still parses, but is missing whatever was added.
+const CACHE_VERSION: u32 = 2;
+
+fn cache_file(cache_dir: &Path, migrations_hash: u64) -> PathBuf {
+    cache_dir.join(format!(
+        "schema-v{CACHE_VERSION}-{migrations_hash:016x}.surql"
+    ))
+}
+
+/// Reads schemas cached by [`write_cached_schemas`] for migrations with the given hash.
+///
+/// Returns `None` if there's nothing cached, or the cache can't be read, in which case the
+/// schemas should be rebuilt from the migrations.
+pub fn read_cached_schemas(cache_dir: &Path, migrations_hash: u64) -> Option<Vec<TableSchema>> {
+    let cached = fs::read_to_string(cache_file(cache_dir, migrations_hash)).ok()?;
+    let query = parse(&cached).ok()?;
+
+    schemas_from_definitions(query.0 .0).ok()
+}
+
+/// Caches schemas on disk, keyed by a hash of the migrations they were built from.
+///
+/// The file is written under a temporary name and then renamed, so parallel builds never see a
+/// partially written cache.
+pub fn write_cached_schemas(
+    cache_dir: &Path,
+    migrations_hash: u64,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    fs::create_dir_all(cache_dir)
+        .with_context(|| format!("could not create {}", cache_dir.to_string_lossy()))?;
+
+    let path = cache_file(cache_dir, migrations_hash);
+    let tmp_path = path.with_extension(format!("surql.{}.tmp", process::id()));
+
+    fs::write(&tmp_path, definitions(schemas))
+        .with_context(|| format!("could not write {}", tmp_path.to_string_lossy()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("could not write {}", path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{read_cached_schemas, write_cached_schemas};
+    use crate::{
+        hash_migration_files, parse_migration_files, read_migration_files,
+        schema::{definitions, get_schemas_from_migrations},
+        Error,
+    };
+
+    #[tokio::test]
+    async fn test_cached_schemas_round_trip() -> Result<(), Error> {
+        let cache_dir = env::temp_dir().join(format!("blackbird-cache-test-{}", process::id()));
+        let files = read_migration_files("../blackbird/examples/migrations")?;
+        let hash = hash_migration_files(&files);
+        let schemas = get_schemas_from_migrations(parse_migration_files(&files)?).await?;
+
+        assert!(read_cached_schemas(&cache_dir, hash).is_none());
+
+        write_cached_schemas(&cache_dir, hash, &schemas)?;
+        let cached = read_cached_schemas(&cache_dir, hash).unwrap();
+
+        assert_eq!(definitions(&schemas), definitions(&cached));
+        assert!(read_cached_schemas(&cache_dir, hash + 1).is_none());
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,2025 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote};
+use surrealdb::sql::Kind;
+use syn::{parse_quote, punctuated::Punctuated, Attribute, Path, Token, Type};
+
+use super::{
+    query::{field_path, Column, ColumnType},
+    read_migrations,
+    schema::{
+        allowed_values, field_name, field_value, get_schemas_from_migrations, is_nullable,
+        linked_table, FieldValue, TableSchema,
+    },
+    snapshot::Snapshot,
+    validate::{constraints, Comparison, Constraint, Literal},
+    Context, Error,
+};
+
+pub const TABLE_ENUM_NAME: &str = "Table";
+
+/// The field holding everything else stored in a schemaless table.
+pub const EXTRA_FIELD: &str = "extra";
+
+pub fn rust_type_for(kind: &Kind) -> TokenStream {
+    match kind {
+        Kind::Bool => quote!(bool),
+        Kind::Datetime => quote!(::surrealdb::sql::Datetime),
+        Kind::Decimal => quote!(::surrealdb::sql::Number),
+        Kind::Duration => quote!(::surrealdb::sql::Duration),
+        Kind::Float => quote!(f64),
+        Kind::Int => quote!(i64),
+        Kind::Number => quote!(::surrealdb::sql::Number),
+        Kind::String => quote!(String),
+        Kind::Object => quote!(::surrealdb::sql::Object),
+        Kind::Record(_) => quote!(::surrealdb::sql::Thing),
+        Kind::Geometry(_) => quote!(::surrealdb::sql::Geometry),
+        Kind::Any => quote!(::surrealdb::sql::Value),
+        Kind::Array => quote!(Vec<::surrealdb::sql::Value>),
+    }
+}
+
+/// Whether `c` can be part of a word in an identifier, i.e. is `XID_Continue` and not `_`.
+fn is_word_char(c: char) -> bool {
+    c != '_' && unicode_ident::is_xid_continue(c)
+}
+
+/// Converts `name` to PascalCase, treating anything that can't be in an identifier, and
+/// underscores, as a word boundary.
+pub fn to_pascal_case(name: &str) -> String {
+    name.split(|c: char| !is_word_char(c))
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Converts `name` to snake_case, treating anything that can't be in an identifier, and
+/// underscores, as a word boundary.
+pub fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    let mut prev_lower = false;
+
+    for c in name.chars() {
+        if !is_word_char(c) {
+            if !snake.is_empty() && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            prev_lower = false;
+            continue;
+        }
+
+        if c.is_uppercase() && prev_lower {
+            snake.push('_');
+        }
+
+        snake.extend(c.to_lowercase());
+        prev_lower = c.is_lowercase() || c.is_numeric();
+    }
+
+    snake.trim_end_matches('_').to_string()
+}
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Keywords that can't be used as raw identifiers either.
+const NON_RAW_KEYWORDS: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Whether `name` is a valid identifier, following the same `XID_Start`/`XID_Continue` rules as
+/// rust, so names like `größe` are kept as they are.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if unicode_ident::is_xid_start(c) || c == '_' => {
+            name != "_" && chars.all(unicode_ident::is_xid_continue)
+        }
+        _ => false,
+    }
+}
+
+/// Makes an identifier for `name`, which is expected to already be in the right case.
+///
+/// Keywords become raw identifiers (`r#type`), or get a trailing underscore when they can't be
+/// raw (`self_`), and names starting with a digit get a leading underscore.
+fn make_ident(name: &str, original: &str) -> Result<Ident, Error> {
+    let name = match name.chars().next() {
+        Some(c) if unicode_ident::is_xid_continue(c) && !unicode_ident::is_xid_start(c) => {
+            format!("_{name}")
+        }
+        _ => name.to_string(),
+    };
+
+    if !is_identifier(&name) {
+        return Err(Error::InvalidIdentifier(original.to_string()));
+    }
+
+    if NON_RAW_KEYWORDS.contains(&name.as_str()) {
+        Ok(Ident::new(&format!("{name}_"), Span::call_site()))
+    } else if KEYWORDS.contains(&name.as_str()) {
+        Ok(Ident::new_raw(&name, Span::call_site()))
+    } else {
+        Ok(Ident::new(&name, Span::call_site()))
+    }
+}
+
+/// The name of the rust field for a field in the database. Names that aren't valid snake_case
+/// identifiers, like `first-name`, `lastName` or `address.city`, are converted to snake_case.
+/// Conversions to and from `Value`s always use the name in the database.
+pub fn field_ident(name: &str) -> Result<Ident, Error> {
+    if is_identifier(name) && !name.chars().any(char::is_uppercase) {
+        make_ident(name, name)
+    } else {
+        make_ident(&to_snake_case(name), name)
+    }
+}
+
+/// The name of the struct generated for a table, e.g. `BlogPost` for `blog_post`.
+pub fn struct_name(table: &str) -> Result<Ident, Error> {
+    make_ident(&to_pascal_case(table), table)
+}
+
+/// Makes identifiers for a set of names in the database, checking that no two end up the same.
+fn unique_field_idents<'a, I>(context: &str, names: I) -> Result<Vec<Ident>, Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut seen: BTreeMap<String, &str> = BTreeMap::new();
+    let mut idents = Vec::new();
+
+    for name in names {
+        let ident = field_ident(name)?;
+        if let Some(first) = seen.insert(ident.to_string(), name) {
+            return Err(Error::FieldNameCollision {
+                context: context.to_string(),
+                first: first.to_string(),
+                second: name.to_string(),
+                ident: ident.to_string(),
+            });
+        }
+
+        idents.push(ident);
+    }
+
+    Ok(idents)
+}
+
+/// `attrs` with `trait_name` taken out of their `#[derive(...)]`s, for structs that always
+/// derive it themselves.
+fn without_derive(attrs: &[Attribute], trait_name: &str) -> Vec<Attribute> {
+    attrs
+        .iter()
+        .filter_map(|attr| {
+            if !attr.path.is_ident("derive") {
+                return Some(attr.clone());
+            }
+            let parser = Punctuated::<Path, Token![,]>::parse_terminated;
+            let traits = match attr.parse_args_with(parser) {
+                Ok(traits) => traits,
+                Err(_) => return Some(attr.clone()),
+            };
+            let kept: Vec<_> = traits
+                .into_iter()
+                .filter(|path| !path.segments.last().is_some_and(|s| s.ident == trait_name))
+                .collect();
+
+            (!kept.is_empty()).then(|| parse_quote!(#[derive(#(#kept),*)]))
+        })
+        .collect()
+}
+
+/// Attributes to put on a generated struct and its fields.
+#[derive(Clone, Default)]
+pub struct StructOptions {
+    pub attrs: Vec<Attribute>,
+    /// Keyed by the name of the field in the database
+    pub field_attrs: BTreeMap<String, Vec<Attribute>>,
+    /// Rust types to use in place of the ones worked out from the schema, keyed by the name of
+    /// the field in the database. These need to implement
+    /// [`FromValue`](crate::convert::FromValue) and [`ToValue`](crate::convert::ToValue), and
+    /// are wrapped in an `Option` when the field is nullable.
+    pub type_overrides: BTreeMap<String, Type>,
+}
+
+impl StructOptions {
+    /// Adds a `#[derive(...)]` for a comma separated list of traits, e.g. `"Debug, Clone"`.
+    pub fn derive(mut self, traits: &str) -> Result<StructOptions, Error> {
+        let traits: TokenStream = traits
+            .parse()
+            .map_err(|e: proc_macro2::LexError| Error::CodegenError(e.to_string()))?;
+
+        self.attrs.push(parse_quote!(#[derive(#traits)]));
+
+        Ok(self)
+    }
+
+    /// Uses `typ`, e.g. `"crate::Email"`, for `field` rather than the type from the schema.
+    pub fn override_type(mut self, field: &str, typ: &str) -> Result<StructOptions, Error> {
+        let typ = syn::parse_str(typ).map_err(|e| {
+            Error::CodegenError(format!("{typ} is not a valid type for {field}: {e}"))
+        })?;
+
+        self.type_overrides.insert(field.to_string(), typ);
+
+        Ok(self)
+    }
+}
+
+fn literal_tokens(literal: &Literal) -> TokenStream {
+    match literal {
+        Literal::None => quote!(::blackbird_core::validate::Literal::None),
+        Literal::Null => quote!(::blackbird_core::validate::Literal::Null),
+        Literal::Bool(b) => quote!(::blackbird_core::validate::Literal::Bool(#b)),
+        Literal::Int(i) => quote!(::blackbird_core::validate::Literal::Int(#i)),
+        Literal::Float(f) => quote!(::blackbird_core::validate::Literal::Float(#f)),
+        Literal::String(s) => quote!(::blackbird_core::validate::Literal::String(#s.to_string())),
+    }
+}
+
+fn comparison_tokens(comparison: Comparison) -> TokenStream {
+    let variant = format_ident!("{:?}", comparison);
+    quote!(::blackbird_core::validate::Comparison::#variant)
+}
+
+fn constraint_tokens(constraint: &Constraint) -> TokenStream {
+    match constraint {
+        Constraint::Compare(c, literal) => {
+            let c = comparison_tokens(*c);
+            let literal = literal_tokens(literal);
+            quote!(::blackbird_core::validate::Constraint::Compare(#c, #literal))
+        }
+        Constraint::Length(c, len) => {
+            let c = comparison_tokens(*c);
+            quote!(::blackbird_core::validate::Constraint::Length(#c, #len))
+        }
+        Constraint::Matches(pattern) => {
+            quote!(::blackbird_core::validate::Constraint::Matches(
+                ::blackbird_core::validate::Pattern::new(#pattern)
+            ))
+        }
+        Constraint::Inside(literals) => {
+            let literals = literals.iter().map(literal_tokens);
+            quote!(::blackbird_core::validate::Constraint::Inside(
+                vec![#(#literals),*]
+            ))
+        }
+    }
+}
+
+/// Generates an enum for a field that can only hold one of a fixed set of strings.
+pub fn enum_items(
+    vis: &TokenStream,
+    name: &Ident,
+    values: &[String],
+) -> Result<TokenStream, Error> {
+    let mut variants: Vec<Ident> = Vec::with_capacity(values.len());
+    for v in values {
+        let variant = struct_name(v)?;
+        if let Some(i) = variants.iter().position(|existing| *existing == variant) {
+            return Err(Error::FieldNameCollision {
+                context: format!("the values of {name}"),
+                first: values[i].clone(),
+                second: v.clone(),
+                ident: variant.to_string(),
+            });
+        }
+
+        variants.push(variant);
+    }
+
+    let name_str = name.to_string();
+    let count = values.len();
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #vis enum #name {
+            #(#variants,)*
+        }
+
+        #[allow(dead_code)]
+        impl #name {
+            pub const ALL: [#name; #count] = [#(#name::#variants,)*];
+
+            /// The value stored in the database
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    #(#name::#variants => #values,)*
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for #name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ::blackbird_core::convert::FromValue for #name {
+            fn from_value(
+                value: ::surrealdb::sql::Value,
+            ) -> ::std::result::Result<Self, ::blackbird_core::Error> {
+                let s = <String as ::blackbird_core::convert::FromValue>::from_value(value)?;
+
+                match s.as_str() {
+                    #(#values => Ok(#name::#variants),)*
+                    _ => Err(::blackbird_core::Error::UnexpectedType(#name_str.to_string(), s)),
+                }
+            }
+        }
+
+        impl ::blackbird_core::convert::ToValue for #name {
+            fn to_value(&self) -> ::surrealdb::sql::Value {
+                ::surrealdb::sql::Value::from(self.as_str())
+            }
+        }
+    })
+}
+
+/// A field of a table, as it appears on the generated structs.
+struct StructField {
+    stored: String,
+    ident: Ident,
+    /// The type when reading the record
+    typ: TokenStream,
+    /// `typ` without the `Option`, for comparing the field against
+    value_typ: TokenStream,
+    /// `value_typ` as seen from the fields module
+    field_ref_typ: TokenStream,
+    nullable: bool,
+    attrs: Vec<TokenStream>,
+    path: TokenStream,
+    /// The keys `path` is made of
+    keys: Vec<String>,
+    value: FieldValue,
+    constraints: Vec<TokenStream>,
+    /// Whether this is the `in` or `out` of an edge, which are set by `RELATE`
+    edge_end: bool,
+}
+
+impl StructField {
+    fn is_written(&self) -> bool {
+        // Computed fields are only ever read, anything we sent would be thrown away
+        !matches!(self.value, FieldValue::Computed(_))
+    }
+
+    /// Checks the field's constraints, when `optional` only if it's been set. The constraints
+    /// are built once, so their regexes are only compiled the first time they're used.
+    fn check(&self, optional: bool) -> TokenStream {
+        if self.constraints.is_empty() {
+            return TokenStream::new();
+        }
+
+        let StructField {
+            stored,
+            ident,
+            constraints,
+            ..
+        } = self;
+
+        let value = if optional {
+            quote!(v)
+        } else {
+            quote!(&self.#ident)
+        };
+        let check = quote! {
+            static CONSTRAINTS: ::std::sync::OnceLock<
+                ::std::vec::Vec<::blackbird_core::validate::Constraint>,
+            > = ::std::sync::OnceLock::new();
+            errors.check(
+                #stored,
+                &::blackbird_core::convert::ToValue::to_value(#value),
+                CONSTRAINTS.get_or_init(|| vec![#(#constraints),*]),
+            );
+        };
+
+        if optional {
+            quote! {
+                if let Some(v) = &self.#ident {
+                    #check
+                }
+            }
+        } else {
+            quote!({ #check })
+        }
+    }
+}
+
+fn validate_fn(checks: TokenStream) -> TokenStream {
+    quote! {
+        /// Checks the parts of each field's `ASSERT` that can be checked without the database.
+        pub fn validate(&self) -> ::std::result::Result<(), ::blackbird_core::validate::ValidationErrors> {
+            #[allow(unused_mut)]
+            let mut errors = ::blackbird_core::validate::ValidationErrors::default();
+            #checks
+            errors.into_result()
+        }
+    }
+}
+
+/// Generates the structs for a table, along with a type for its ids, and conversions to and
+/// from `Value`s. Only the main struct is checked for dead code, as the rest are generated
+/// whether they're needed or not:
+///
+/// - `Person`, a record as it's read back from the database, with its id
+/// - `NewPerson`, for inserting a record, without fields the database fills in
+/// - `PersonPatch`, where every field is optional, for `MERGE`ing into an existing record
+///
+/// Edge tables, with `in` and `out` fields, are created with `RELATE` rather than `INSERT`. When
+/// `in` or `out` link to a single table they use its id type, e.g. `PersonId`, which has to be
+/// generated alongside.
+///
+/// Structs for schemaless tables get an `extra` map holding any fields that aren't defined, which
+/// is written back out along with the rest.
+pub fn table_items(
+    vis: &TokenStream,
+    name: &Ident,
+    schema: &TableSchema,
+    options: &StructOptions,
+) -> Result<TokenStream, Error> {
+    let id_name = format_ident!("{}Id", name);
+    let new_name = format_ident!("New{}", name);
+    let patch_name = format_ident!("{}Patch", name);
+    let table_name = &schema.name;
+    let is_edge = schema.is_edge();
+    let schemaless = schema.is_schemaless();
+
+    if let Some(field) = options
+        .field_attrs
+        .keys()
+        .chain(options.type_overrides.keys())
+        .find(|k| !schema.fields.iter().any(|f| field_name(f) == **k))
+    {
+        return Err(Error::UnknownField {
+            table: schema.name.clone(),
+            field: field.clone(),
+        });
+    }
+
+    let struct_attrs = &options.attrs;
+    // Patches are always `Default`, whether or not the other structs are
+    let patch_attrs = without_derive(struct_attrs, "Default");
+    let stored_names: Vec<String> = schema.fields.iter().map(field_name).collect();
+    let field_idents = unique_field_idents(
+        &format!("table {}", schema.name),
+        stored_names.iter().map(String::as_str),
+    )?;
+    let mut fields = Vec::with_capacity(schema.fields.len());
+    let mut enums = TokenStream::new();
+    let mut override_aliases = TokenStream::new();
+
+    for (f, (stored, ident)) in schema
+        .fields
+        .iter()
+        .zip(stored_names.into_iter().zip(field_idents))
+    {
+        // A value that can't be a variant name (e.g. `''`) leaves the field a plain `String`
+        let values = match f.kind {
+            None | Some(Kind::String) => {
+                allowed_values(f).filter(|values| values.iter().all(|v| struct_name(v).is_ok()))
+            }
+            _ => None,
+        };
+
+        let edge_end = is_edge && (stored == "in" || stored == "out");
+        let end_table = match f.kind.as_ref().and_then(linked_table) {
+            Some(table) if edge_end => Some(format_ident!("{}Id", struct_name(table)?)),
+            _ => None,
+        };
+
+        let typ = match (options.type_overrides.get(&stored), values, f.kind.as_ref()) {
+            (Some(typ), _, _) => quote!(#typ),
+            (None, _, _) if end_table.is_some() => quote!(#end_table),
+            (None, Some(values), _) => {
+                let enum_name = format_ident!("{}{}", name, struct_name(&stored)?);
+                enums.extend(enum_items(vis, &enum_name, &values)?);
+                quote!(#enum_name)
+            }
+            (None, None, Some(kind)) => rust_type_for(kind),
+            (None, None, None) if schemaless => rust_type_for(&Kind::Any),
+            (None, None, None) => {
+                return Err(Error::UntypedField {
+                    table: schema.name.clone(),
+                    field: stored,
+                })
+            }
+        };
+        // Overrides are written relative to the struct, so the fields module reaches them
+        // through an alias next to it
+        let field_ref_typ = if options.type_overrides.contains_key(&stored) {
+            let alias = format_ident!("{}{}Override", name, struct_name(&stored)?);
+            override_aliases.extend(quote! {
+                #[doc(hidden)]
+                #vis type #alias = #typ;
+            });
+            quote!(super::#alias)
+        } else {
+            typ.clone()
+        };
+        // Every edge has both ends, whatever the field's ASSERT says
+        let value_typ = typ.clone();
+        let nullable = is_nullable(f) && !edge_end;
+        let typ = if nullable { quote!(Option<#typ>) } else { typ };
+
+        let value = field_value(f);
+        let mut attrs = Vec::new();
+        if ident.to_string().trim_start_matches("r#") != stored {
+            let doc = format!(" Stored as `{stored}`");
+            attrs.push(quote!(#[doc = #doc]));
+        }
+        match &value {
+            FieldValue::Plain => {}
+            FieldValue::Defaulted(default) => {
+                let doc = format!(" Defaults to `{default}`");
+                attrs.push(quote!(#[doc = #doc]));
+            }
+            FieldValue::Computed(computed) => {
+                let doc = format!(" Set by the database to `{computed}`, so never written");
+                attrs.push(quote!(#[doc = #doc]));
+            }
+        }
+        attrs.extend(
+            options
+                .field_attrs
+                .get(&stored)
+                .into_iter()
+                .flatten()
+                .map(|a| quote!(#a)),
+        );
+
+        let path = field_path(&f.name);
+
+        fields.push(StructField {
+            path: quote!(&[#(#path),*]),
+            keys: path.iter().map(|k| k.to_string()).collect(),
+            constraints: constraints(f).iter().map(constraint_tokens).collect(),
+            stored,
+            ident,
+            typ,
+            value_typ,
+            field_ref_typ,
+            nullable,
+            attrs,
+            value,
+            edge_end,
+        });
+    }
+
+    // Tables can define their own id field, in which case that's what we use
+    let id_field = if fields.iter().any(|f| f.stored == "id") {
+        TokenStream::new()
+    } else {
+        quote!(pub id: #id_name,)
+    };
+    let id_read = if id_field.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! {
+            id: ::blackbird_core::convert::FromValue::from_value(
+                ::blackbird_core::convert::take_path(&mut obj, &["id"]),
+            )?,
+        }
+    };
+    let id_write = if id_field.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! {
+            ::blackbird_core::convert::insert_path(
+                &mut obj,
+                &["id"],
+                ::blackbird_core::convert::ToValue::to_value(&self.id),
+            );
+        }
+    };
+
+    // Schemaless tables can hold anything, so whatever isn't a defined field is kept in `extra`
+    let (extra_field, extra_read, obj_init) = if schemaless {
+        if let Some(f) = fields.iter().find(|f| f.ident == EXTRA_FIELD) {
+            return Err(Error::FieldNameCollision {
+                context: format!("table {}", schema.name),
+                first: f.stored.clone(),
+                second: "the catch-all for undefined fields".to_string(),
+                ident: EXTRA_FIELD.to_string(),
+            });
+        }
+
+        let extra = format_ident!("{}", EXTRA_FIELD);
+        (
+            quote! {
+                /// Anything stored that isn't a defined field
+                pub #extra: ::std::collections::BTreeMap<String, ::surrealdb::sql::Value>,
+            },
+            quote!(#extra: obj.0,),
+            quote!(::surrealdb::sql::Object(self.#extra.clone())),
+        )
+    } else {
+        (
+            TokenStream::new(),
+            TokenStream::new(),
+            quote!(::surrealdb::sql::Object::default()),
+        )
+    };
+
+    let written: Vec<&StructField> = fields.iter().filter(|f| f.is_written()).collect();
+    let id_items = id_items(vis, &id_name, table_name);
+
+    // `person::username` and friends, for building queries with `Person::select()`
+    let fields_module = field_ident(&to_snake_case(&name.to_string()))?;
+    // The constants need to be visible wherever the struct is, which for anything less than
+    // `pub(crate)` means reaching back out of the module
+    let const_vis = match vis.to_string().replace(' ', "").as_str() {
+        "pub" | "pub(crate)" => vis.clone(),
+        _ => quote!(pub(super)),
+    };
+    let id_const = if id_field.is_empty() {
+        TokenStream::new()
+    } else {
+        quote! {
+            #const_vis const id: ::blackbird_core::select::FieldRef<#id_name> =
+                ::blackbird_core::select::FieldRef::new(&["id"]);
+        }
+    };
+    // Keyset paging needs a unique order, and NONEs would drop out of it
+    let list_by_fns = fields
+        .iter()
+        .filter(|f| !f.nullable && schema.has_unique_index(&f.stored))
+        .map(|f| {
+            let StructField {
+                stored,
+                ident,
+                value_typ,
+                ..
+            } = f;
+            let fn_name = format_ident!("list_by_{}", ident.to_string().trim_start_matches("r#"));
+            let doc = format!(" Pages through the table in order of its unique `{stored}` index");
+
+            quote! {
+                #[doc = #doc]
+                pub async fn #fn_name(
+                    ds: &::surrealdb::Datastore,
+                    sess: &::surrealdb::Session,
+                    after: Option<#value_typ>,
+                    size: u64,
+                ) -> ::std::result::Result<
+                    ::blackbird_core::select::Page<Self, #value_typ>,
+                    ::blackbird_core::Error,
+                > {
+                    Self::select()
+                        .fetch_page_after(ds, sess, #fields_module::#ident, after, size)
+                        .await
+                }
+            }
+        });
+    let field_consts = fields.iter().map(|f| {
+        let StructField {
+            ident,
+            field_ref_typ,
+            path,
+            ..
+        } = f;
+        quote! {
+            #const_vis const #ident: ::blackbird_core::select::FieldRef<#field_ref_typ> =
+                ::blackbird_core::select::FieldRef::new(#path);
+        }
+    });
+
+    let read_fields = fields.iter().map(|f| {
+        let StructField {
+            ident, typ, attrs, ..
+        } = f;
+        quote!(#(#attrs)* pub #ident: #typ,)
+    });
+    let decoded_fields = decode_order(fields.iter().map(|f| f.keys.as_slice()))
+        .into_iter()
+        .map(|(i, nested)| {
+            let StructField { ident, path, .. } = &fields[i];
+            decode_field(ident, path, nested)
+        });
+    let read_checks: TokenStream = written.iter().map(|f| f.check(false)).collect();
+    let read_validate = validate_fn(read_checks);
+    let read_writes = written.iter().map(|f| {
+        let StructField { ident, path, .. } = f;
+        quote! {
+            ::blackbird_core::convert::insert_path(
+                &mut obj,
+                #path,
+                ::blackbird_core::convert::ToValue::to_value(&self.#ident),
+            );
+        }
+    });
+
+    // Defaulted fields can be left out when inserting
+    let new_fields = written.iter().map(|f| {
+        let StructField {
+            ident, typ, attrs, ..
+        } = f;
+        match f.value {
+            FieldValue::Defaulted(_) => quote!(#(#attrs)* pub #ident: Option<#typ>,),
+            _ => quote!(#(#attrs)* pub #ident: #typ,),
+        }
+    });
+    let new_checks: TokenStream = written
+        .iter()
+        .map(|f| f.check(matches!(f.value, FieldValue::Defaulted(_))))
+        .collect();
+    let new_validate = validate_fn(new_checks);
+    let new_idents = written.iter().map(|f| &f.ident);
+    let new_paths = written.iter().map(|f| &f.path);
+
+    // Defaulted fields may be left out of a `New` struct, so they can't be matched on
+    let upsert_by_fns = written
+        .iter()
+        .filter(|f| {
+            !f.nullable
+                && !matches!(f.value, FieldValue::Defaulted(_))
+                && schema.has_unique_index(&f.stored)
+        })
+        .map(|f| {
+            let StructField { stored, ident, .. } = f;
+            let fn_name = format_ident!(
+                "upsert_many_by_{}",
+                ident.to_string().trim_start_matches("r#")
+            );
+            let doc = format!(
+                " Merges each row into the record with the same `{stored}`, inserting the rows \
+                 that don't match one"
+            );
+
+            quote! {
+                #[doc = #doc]
+                pub async fn #fn_name(
+                    ds: &::surrealdb::Datastore,
+                    sess: &::surrealdb::Session,
+                    rows: &[Self],
+                ) -> ::std::result::Result<::std::vec::Vec<#name>, ::blackbird_core::Error> {
+                    ::blackbird_core::bulk::upsert_many_by(
+                        ds,
+                        sess,
+                        #id_name::TABLE,
+                        #fields_module::#ident,
+                        rows,
+                    )
+                    .await
+                }
+            }
+        });
+
+    // Edges are created with their ends and can't be moved, so the rest goes in the content
+    let (create_fn, patched): (TokenStream, Vec<&StructField>) = if is_edge {
+        let content: Vec<&StructField> = written.iter().copied().filter(|f| !f.edge_end).collect();
+        let content_idents = content.iter().map(|f| &f.ident);
+        let content_paths = content.iter().map(|f| &f.path);
+
+        let relate = quote! {
+            /// A `RELATE` from `in` to `out` with the rest of the fields as its content.
+            pub fn relate_statement(&self) -> ::surrealdb::sql::Statement {
+                #[allow(unused_mut)]
+                let mut obj = #obj_init;
+                #(::blackbird_core::convert::insert_path(
+                    &mut obj,
+                    #content_paths,
+                    ::blackbird_core::convert::ToValue::to_value(&self.#content_idents),
+                );)*
+
+                ::surrealdb::sql::Statement::Relate(::surrealdb::sql::statements::RelateStatement {
+                    kind: #table_name.into(),
+                    from: ::blackbird_core::convert::ToValue::to_value(&self.r#in),
+                    with: ::blackbird_core::convert::ToValue::to_value(&self.out),
+                    data: Some(::surrealdb::sql::Data::ContentExpression(
+                        ::surrealdb::sql::Value::Object(obj),
+                    )),
+                    ..::std::default::Default::default()
+                })
+            }
+        };
+
+        (relate, content)
+    } else {
+        let insert = quote! {
+            pub fn insert_statement(&self) -> ::surrealdb::sql::Statement {
+                ::surrealdb::sql::Statement::Insert(::surrealdb::sql::statements::InsertStatement {
+                    into: #table_name.into(),
+                    data: ::surrealdb::sql::Data::SingleExpression(
+                        ::blackbird_core::convert::ToValue::to_value(self),
+                    ),
+                    ..::std::default::Default::default()
+                })
+            }
+
+            /// Inserts all of `rows`, in batches of [`::blackbird_core::bulk::CHUNK_SIZE`].
+            pub async fn insert_many(
+                ds: &::surrealdb::Datastore,
+                sess: &::surrealdb::Session,
+                rows: &[Self],
+            ) -> ::std::result::Result<::std::vec::Vec<#name>, ::blackbird_core::Error> {
+                ::blackbird_core::bulk::insert_many(ds, sess, #id_name::TABLE, rows).await
+            }
+
+            #(#upsert_by_fns)*
+        };
+
+        (insert, written.clone())
+    };
+
+    let patch_fields = patched.iter().map(|f| {
+        let StructField {
+            ident, typ, attrs, ..
+        } = f;
+        quote!(#(#attrs)* pub #ident: Option<#typ>,)
+    });
+    let patch_checks: TokenStream = patched.iter().map(|f| f.check(true)).collect();
+    let patch_validate = validate_fn(patch_checks);
+    let patch_idents = patched.iter().map(|f| &f.ident);
+    let patch_paths = patched.iter().map(|f| &f.path);
+
+    Ok(quote! {
+        #(#struct_attrs)*
+        #vis struct #name {
+            #id_field
+            #(#read_fields)*
+            #extra_field
+        }
+
+        #[allow(dead_code)]
+        impl #name {
+            #read_validate
+
+            pub fn select() -> ::blackbird_core::select::SelectBuilder<#name> {
+                ::blackbird_core::select::SelectBuilder::new(#id_name::TABLE)
+            }
+
+            /// Pages through the table with `LIMIT` and `START`
+            pub async fn list(
+                ds: &::surrealdb::Datastore,
+                sess: &::surrealdb::Session,
+                start: u64,
+                size: u64,
+            ) -> ::std::result::Result<
+                ::blackbird_core::select::Page<Self, u64>,
+                ::blackbird_core::Error,
+            > {
+                Self::select().fetch_page(ds, sess, start, size).await
+            }
+
+            #(#list_by_fns)*
+
+            /// Creates or replaces each row by its id.
+            pub async fn upsert_many(
+                ds: &::surrealdb::Datastore,
+                sess: &::surrealdb::Session,
+                rows: &[Self],
+            ) -> ::std::result::Result<::std::vec::Vec<Self>, ::blackbird_core::Error> {
+                ::blackbird_core::bulk::upsert_many(ds, sess, rows).await
+            }
+        }
+
+        impl ::blackbird_core::convert::FromValue for #name {
+            fn from_value(
+                value: ::surrealdb::sql::Value,
+            ) -> ::std::result::Result<Self, ::blackbird_core::Error> {
+                let mut obj =
+                    <::surrealdb::sql::Object as ::blackbird_core::convert::FromValue>::from_value(
+                        value,
+                    )?;
+
+                Ok(Self {
+                    #id_read
+                    #(#decoded_fields)*
+                    #extra_read
+                })
+            }
+        }
+
+        impl ::blackbird_core::convert::ToValue for #name {
+            fn to_value(&self) -> ::surrealdb::sql::Value {
+                #[allow(unused_mut)]
+                let mut obj = #obj_init;
+                #id_write
+                #(#read_writes)*
+
+                ::surrealdb::sql::Value::Object(obj)
+            }
+        }
+
+        #(#struct_attrs)*
+        #[allow(dead_code)]
+        #vis struct #new_name {
+            #(#new_fields)*
+            #extra_field
+        }
+
+        #[allow(dead_code)]
+        impl #new_name {
+            #new_validate
+
+            #create_fn
+        }
+
+        impl ::blackbird_core::convert::ToValue for #new_name {
+            fn to_value(&self) -> ::surrealdb::sql::Value {
+                #[allow(unused_mut)]
+                let mut obj = #obj_init;
+                #(::blackbird_core::convert::insert_path(
+                    &mut obj,
+                    #new_paths,
+                    ::blackbird_core::convert::ToValue::to_value(&self.#new_idents),
+                );)*
+
+                ::surrealdb::sql::Value::Object(obj)
+            }
+        }
+
+        #(#patch_attrs)*
+        #[derive(Default)]
+        #[allow(dead_code)]
+        #vis struct #patch_name {
+            #(#patch_fields)*
+            #extra_field
+        }
+
+        #[allow(dead_code)]
+        impl #patch_name {
+            #patch_validate
+
+            /// An `UPDATE ... MERGE` setting every field that's `Some`. Nullable fields can be
+            /// cleared by setting them to `Some(None)`.
+            pub fn merge_statement(&self, id: &#id_name) -> ::surrealdb::sql::Statement {
+                ::surrealdb::sql::Statement::Update(::surrealdb::sql::statements::UpdateStatement {
+                    what: ::surrealdb::sql::Values(vec![::surrealdb::sql::Value::Thing(id.thing())]),
+                    data: Some(::surrealdb::sql::Data::MergeExpression(
+                        ::blackbird_core::convert::ToValue::to_value(self),
+                    )),
+                    ..::std::default::Default::default()
+                })
+            }
+        }
+
+        impl ::blackbird_core::convert::ToValue for #patch_name {
+            fn to_value(&self) -> ::surrealdb::sql::Value {
+                #[allow(unused_mut)]
+                let mut obj = #obj_init;
+                #(if let Some(v) = &self.#patch_idents {
+                    ::blackbird_core::convert::set_path(
+                        &mut obj,
+                        #patch_paths,
+                        ::blackbird_core::convert::ToValue::to_value(v),
+                    );
+                })*
+
+                ::surrealdb::sql::Value::Object(obj)
+            }
+        }
+
+        #enums
+
+        #override_aliases
+
+        #id_items
+
+        #[allow(dead_code, non_upper_case_globals)]
+        #vis mod #fields_module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #id_const
+            #(#field_consts)*
+        }
+    })
+}
+
+/// Generates a read only struct for a table defined `AS SELECT ...`, with fields worked out from
+/// the table it selects from, along with an id type. There's no `New` or `Patch` struct, since
+/// the database maintains the records itself.
+///
+/// Views whose fields can't be worked out, like ones selecting from more than one table, get a
+/// struct with just the id and a map of everything else, rather than failing the whole schema.
+pub fn view_items(
+    vis: &TokenStream,
+    name: &Ident,
+    schema: &TableSchema,
+    schemas: &[TableSchema],
+    options: &StructOptions,
+) -> Result<TokenStream, Error> {
+    // Views don't define fields, so there's nothing for these to apply to
+    if let Some(field) = options
+        .field_attrs
+        .keys()
+        .chain(options.type_overrides.keys())
+        .next()
+    {
+        return Err(Error::UnknownField {
+            table: schema.name.clone(),
+            field: field.clone(),
+        });
+    }
+
+    let extra = format_ident!("{}", EXTRA_FIELD);
+    let (columns, extra_field, extra_read) = match schema.view_columns(schemas)? {
+        Some(columns) => (columns, TokenStream::new(), TokenStream::new()),
+        None => (
+            Vec::new(),
+            quote! {
+                /// Every field of the record, since the view's fields couldn't be worked out
+                pub #extra: ::std::collections::BTreeMap<String, ::surrealdb::sql::Value>,
+            },
+            quote!(#extra: obj.0,),
+        ),
+    };
+    // Records in a view have ids in the view's table, not the one it selects from
+    let columns: Vec<Column> = columns.into_iter().filter(|c| c.name != "id").collect();
+
+    let id_name = format_ident!("{}Id", name);
+    let struct_attrs = &options.attrs;
+    let field_names = unique_field_idents(
+        &format!("view {}", schema.name),
+        columns.iter().map(|c| c.name.as_str()),
+    )?;
+    let mut nested = TokenStream::new();
+    let (field_types, field_paths) = column_fields(name, &columns, &mut nested)?;
+    let decoded_fields = column_reads(&columns, &field_names, &field_paths);
+    let id_items = id_items(vis, &id_name, &schema.name);
+
+    Ok(quote! {
+        #(#struct_attrs)*
+        #vis struct #name {
+            pub id: #id_name,
+            #(pub #field_names: #field_types,)*
+            #extra_field
+        }
+
+        impl ::blackbird_core::convert::FromValue for #name {
+            fn from_value(
+                value: ::surrealdb::sql::Value,
+            ) -> ::std::result::Result<Self, ::blackbird_core::Error> {
+                let mut obj =
+                    <::surrealdb::sql::Object as ::blackbird_core::convert::FromValue>::from_value(
+                        value,
+                    )?;
+
+                Ok(Self {
+                    id: ::blackbird_core::convert::FromValue::from_value(
+                        ::blackbird_core::convert::take_path(&mut obj, &["id"]),
+                    )?,
+                    #(#decoded_fields)*
+                    #extra_read
+                })
+            }
+        }
+
+        #nested
+
+        #id_items
+    })
+}
+
+/// Generates the items for `schema`, using [`view_items`] for views and [`table_items`] for
+/// everything else. `schemas` is needed to work out the fields of views.
+pub fn struct_items(
+    vis: &TokenStream,
+    name: &Ident,
+    schema: &TableSchema,
+    schemas: &[TableSchema],
+    options: &StructOptions,
+) -> Result<TokenStream, Error> {
+    if schema.is_view() {
+        view_items(vis, name, schema, schemas, options)
+    } else {
+        table_items(vis, name, schema, options)
+    }
+}
+
+/// Generates `PersonId`, which wraps the `Id` part of a record in the table.
+fn id_items(vis: &TokenStream, id_name: &Ident, table_name: &str) -> TokenStream {
+    quote! {
+        #[derive(Debug, Clone, PartialEq)]
+        #vis struct #id_name(pub ::surrealdb::sql::Id);
+
+        #[allow(dead_code)]
+        impl #id_name {
+            pub const TABLE: &'static str = #table_name;
+
+            pub fn thing(&self) -> ::surrealdb::sql::Thing {
+                ::surrealdb::sql::Thing {
+                    tb: Self::TABLE.to_string(),
+                    id: self.0.clone(),
+                }
+            }
+        }
+
+        impl ::blackbird_core::convert::FromValue for #id_name {
+            fn from_value(
+                value: ::surrealdb::sql::Value,
+            ) -> ::std::result::Result<Self, ::blackbird_core::Error> {
+                let thing =
+                    <::surrealdb::sql::Thing as ::blackbird_core::convert::FromValue>::from_value(
+                        value,
+                    )?;
+
+                if thing.tb == Self::TABLE {
+                    Ok(Self(thing.id))
+                } else {
+                    Err(::blackbird_core::Error::UnexpectedType(
+                        format!("record in {}", Self::TABLE),
+                        format!("{:?}", thing),
+                    ))
+                }
+            }
+        }
+
+        impl ::blackbird_core::convert::ToValue for #id_name {
+            fn to_value(&self) -> ::surrealdb::sql::Value {
+                ::surrealdb::sql::Value::Thing(self.thing())
+            }
+        }
+    }
+}
+
+/// The name of the method fetching the records an edge leads to, e.g. `posts` for `post`.
+fn targets_ident(table: &str) -> Result<Ident, Error> {
+    let snake = to_snake_case(table);
+    if snake.ends_with('s') {
+        field_ident(&snake)
+    } else {
+        field_ident(&format!("{snake}s"))
+    }
+}
+
+/// Generates traversals like `person.likes().posts(&ds, &sess)` for an edge, when both of its
+/// ends link to a single table in `schemas`.
+fn traversal_items(edge: &TableSchema, schemas: &[TableSchema]) -> Result<TokenStream, Error> {
+    let end = |name: &str| {
+        let table = edge
+            .field(name)
+            .and_then(|f| f.kind.as_ref())
+            .and_then(linked_table)?;
+        schemas.iter().find(|s| s.name == table)
+    };
+    let (from, to) = match (end("in"), end("out")) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(TokenStream::new()),
+    };
+
+    let edge_name = struct_name(&edge.name)?;
+    let edge_id = format_ident!("{}Id", edge_name);
+    let from_name = struct_name(&from.name)?;
+    let from_id = format_ident!("{}Id", from_name);
+    let to_name = struct_name(&to.name)?;
+    let to_id = format_ident!("{}Id", to_name);
+    let traversal_name = format_ident!("{}From{}", edge_name, from_name);
+    let edge_method = field_ident(&edge.name)?;
+    let targets_method = targets_ident(&to.name)?;
+
+    let doc = format!(" `{}` edges leading out of a `{}`", edge.name, from.name);
+    let targets_doc = format!(" The `{}` records at the other end of the edges", to.name);
+
+    // Tables defining their own id don't have a `PersonId` on the struct to start from
+    let from_record = if from.field("id").is_none() {
+        quote! {
+            #[allow(dead_code)]
+            impl #from_name {
+                pub fn #edge_method(&self) -> #traversal_name {
+                    self.id.#edge_method()
+                }
+            }
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    Ok(quote! {
+        #[doc = #doc]
+        pub struct #traversal_name(pub ::blackbird_core::graph::Traversal);
+
+        #[allow(dead_code)]
+        impl #traversal_name {
+            pub async fn edges(
+                &self,
+                ds: &::surrealdb::Datastore,
+                sess: &::surrealdb::Session,
+            ) -> ::std::result::Result<Vec<#edge_name>, ::blackbird_core::Error> {
+                self.0.fetch_edges(ds, sess).await
+            }
+
+            #[doc = #targets_doc]
+            pub async fn #targets_method(
+                &self,
+                ds: &::surrealdb::Datastore,
+                sess: &::surrealdb::Session,
+            ) -> ::std::result::Result<Vec<#to_name>, ::blackbird_core::Error> {
+                self.0.fetch_targets(ds, sess, #to_id::TABLE).await
+            }
+        }
+
+        #[allow(dead_code)]
+        impl #from_id {
+            pub fn #edge_method(&self) -> #traversal_name {
+                #traversal_name(::blackbird_core::graph::Traversal::new(
+                    self.thing(),
+                    #edge_id::TABLE,
+                ))
+            }
+        }
+
+        #from_record
+    })
+}
+
+/// Generates public items for every table, along with a `Table` enum listing them. `options` are
+/// keyed by table name.
+pub fn schema_items(
+    schemas: &[TableSchema],
+    options: &BTreeMap<String, StructOptions>,
+) -> Result<TokenStream, Error> {
+    if let Some(table) = options
+        .keys()
+        .find(|t| !schemas.iter().any(|s| s.name == **t))
+    {
+        return Err(Error::UnknownTable(table.clone()));
+    }
+
+    let no_options = StructOptions::default();
+    let table_enum = Ident::new(TABLE_ENUM_NAME, Span::call_site());
+    let table_count = schemas.len();
+    let mut tables = TokenStream::new();
+    let mut variants = Vec::with_capacity(table_count);
+    let mut table_names = Vec::with_capacity(table_count);
+
+    for schema in schemas.iter() {
+        let name = struct_name(&schema.name)?;
+        if name == TABLE_ENUM_NAME {
+            return Err(Error::NameClash(
+                schema.name.clone(),
+                TABLE_ENUM_NAME.to_string(),
+            ));
+        }
+
+        let struct_options = options.get(&schema.name).unwrap_or(&no_options);
+        tables.extend(struct_items(
+            &quote!(pub),
+            &name,
+            schema,
+            schemas,
+            struct_options,
+        )?);
+        tables.extend(quote! {
+            impl #name {
+                pub const TABLE: #table_enum = #table_enum::#name;
+            }
+        });
+
+        variants.push(name);
+        table_names.push(&schema.name);
+    }
+
+    for edge in schemas.iter().filter(|s| s.is_edge()) {
+        tables.extend(traversal_items(edge, schemas)?);
+    }
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum #table_enum {
+            #(#variants,)*
+        }
+
+        impl #table_enum {
+            pub const ALL: [#table_enum; #table_count] = [#(#table_enum::#variants,)*];
+
+            /// The name of the table in the database
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    #(#table_enum::#variants => #table_names,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    #(#table_names => Some(#table_enum::#variants),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl ::std::fmt::Display for #table_enum {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        #tables
+    })
+}
+
+/// Generates a struct for the rows returned by a query, along with any structs for fetched
+/// records it contains.
+pub fn row_items(name: &Ident, columns: &[Column]) -> Result<TokenStream, Error> {
+    let field_names = unique_field_idents("the query", columns.iter().map(|c| c.name.as_str()))?;
+    let mut nested = TokenStream::new();
+    let (field_types, field_paths) = column_fields(name, columns, &mut nested)?;
+    let decoded_fields = column_reads(columns, &field_names, &field_paths);
+
+    Ok(quote! {
+        #[derive(Debug, Clone)]
+        pub struct #name {
+            #(pub #field_names: #field_types,)*
+        }
+
+        impl ::blackbird_core::convert::FromValue for #name {
+            fn from_value(
+                value: ::surrealdb::sql::Value,
+            ) -> ::std::result::Result<Self, ::blackbird_core::Error> {
+                let mut obj =
+                    <::surrealdb::sql::Object as ::blackbird_core::convert::FromValue>::from_value(
+                        value,
+                    )?;
+
+                Ok(Self {
+                    #(#decoded_fields)*
+                })
+            }
+        }
+
+        #nested
+    })
+}
+
+/// The order to read fields out of a record in, given the keys leading to each, along with
+/// whether each is nested inside another field.
+///
+/// Taking `address` out of the record takes `address.city` with it, so nested fields are read
+/// first, and copied rather than taken so the field they're in still gets all of its value.
+fn decode_order<'a, I>(paths: I) -> Vec<(usize, bool)>
+where
+    I: IntoIterator<Item = &'a [String]>,
+{
+    let paths: Vec<&[String]> = paths.into_iter().collect();
+    let mut order: Vec<(usize, bool)> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let nested = paths
+                .iter()
+                .any(|other| other.len() < path.len() && path.starts_with(other));
+            (i, nested)
+        })
+        .collect();
+
+    order.sort_by_key(|&(i, _)| (Reverse(paths[i].len()), i));
+    order
+}
+
+/// Reads one field of a struct out of `obj`, in a struct expression.
+fn decode_field(ident: &Ident, path: &TokenStream, nested: bool) -> TokenStream {
+    let value = if nested {
+        quote!(::blackbird_core::convert::get_path(&obj, #path))
+    } else {
+        quote!(::blackbird_core::convert::take_path(&mut obj, #path))
+    };
+
+    quote! {
+        #ident: ::blackbird_core::convert::FromValue::from_value(#value)?,
+    }
+}
+
+/// Reads the fields for `columns`, in [`decode_order`].
+fn column_reads(
+    columns: &[Column],
+    field_names: &[Ident],
+    field_paths: &[TokenStream],
+) -> Vec<TokenStream> {
+    decode_order(columns.iter().map(|c| c.path.as_slice()))
+        .into_iter()
+        .map(|(i, nested)| decode_field(&field_names[i], &field_paths[i], nested))
+        .collect()
+}
+
+/// The types and paths of the fields for `columns`, adding structs for any fetched records to
+/// `nested`.
+fn column_fields(
+    name: &Ident,
+    columns: &[Column],
+    nested: &mut TokenStream,
+) -> Result<(Vec<TokenStream>, Vec<TokenStream>), Error> {
+    let mut field_types = Vec::with_capacity(columns.len());
+    let mut field_paths = Vec::with_capacity(columns.len());
+
+    for c in columns {
+        let typ = match &c.ty {
+            ColumnType::Value {
+                kind: Some(kind),
+                nullable,
+            } => {
+                let typ = rust_type_for(kind);
+                if *nullable {
+                    quote!(Option<#typ>)
+                } else {
+                    typ
+                }
+            }
+            // Value already covers NONE and NULL
+            ColumnType::Value { kind: None, .. } => quote!(::surrealdb::sql::Value),
+            ColumnType::Fetched { columns, nullable } => {
+                let nested_name = format_ident!("{}{}", name, to_pascal_case(&c.name));
+                nested.extend(row_items(&nested_name, columns)?);
+                if *nullable {
+                    quote!(Option<#nested_name>)
+                } else {
+                    quote!(#nested_name)
+                }
+            }
+        };
+
+        let path = &c.path;
+        field_types.push(typ);
+        field_paths.push(quote!(&[#(#path),*]));
+    }
+
+    Ok((field_types, field_paths))
+}
+
+/// Writes rust source for every table to a file, for use from a build script as an alternative
+/// to the macros.
+///
+/// ```ignore
+/// // build.rs
+/// fn main() {
+///     println!("cargo:rerun-if-changed=src/migrations");
+///
+///     blackbird_core::codegen::Generator::from_migrations("src/migrations")
+///         .unwrap()
+///         .write_to_out_dir("schema.rs")
+///         .unwrap();
+/// }
+///
+/// // lib.rs
+/// mod schema {
+///     include!(concat!(env!("OUT_DIR"), "/schema.rs"));
+/// }
+/// ```
+pub struct Generator {
+    schemas: Vec<TableSchema>,
+    options: BTreeMap<String, StructOptions>,
+}
+
+impl Generator {
+    pub fn new(schemas: Vec<TableSchema>) -> Generator {
+        Generator {
+            schemas,
+            options: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the attributes for the struct generated for `table`.
+    ///
+    /// ```ignore
+    /// generator.with_options("person", StructOptions::default().derive("Debug, Clone")?)
+    /// ```
+    pub fn with_options(mut self, table: &str, options: StructOptions) -> Generator {
+        self.options.insert(table.to_string(), options);
+        self
+    }
+
+    /// Applies the migrations in `directory` to an in memory database to work out the schema.
+    ///
+    /// This blocks while it does so, so it shouldn't be called from inside an async runtime.
+    pub fn from_migrations<P>(directory: P) -> Result<Generator, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let migrations = read_migrations(directory)?;
+        let schemas = tokio::runtime::Runtime::new()
+            .context("could not start a runtime to apply migrations".to_string())?
+            .block_on(get_schemas_from_migrations(migrations))?;
+
+        Ok(Generator::new(schemas))
+    }
+
+    /// Uses a snapshot written by `blackbird schema dump`, rather than applying the migrations.
+    pub fn from_snapshot<P>(directory: P) -> Result<Generator, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Generator::new(Snapshot::read(directory)?.schemas))
+    }
+
+    pub fn tokens(&self) -> Result<TokenStream, Error> {
+        schema_items(&self.schemas, &self.options)
+    }
+
+    /// Formatted rust source for all of the tables.
+    pub fn generate(&self) -> Result<String, Error> {
+        let file = syn::parse2::<syn::File>(self.tokens()?)
+            .map_err(|e| Error::CodegenError(e.to_string()))?;
+
+        Ok(format!(
+            "// @generated by blackbird from the schema produced by the migrations, do not edit.\n\n{}",
+            prettyplease::unparse(&file)
+        ))
+    }
+
+    /// Writes the generated source to `path`, unless it's already there. Leaving the file alone
+    /// when nothing has changed stops cargo rebuilding everything that includes it.
+    pub fn write_to<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let generated = self.generate()?;
+
+        if fs::read_to_string(path).ok().as_ref() == Some(&generated) {
+            return Ok(());
+        }
+
+        fs::write(path, generated)
+            .with_context(|| format!("could not write {}", path.to_string_lossy()))
+    }
+
+    /// Writes the generated source to `file_name` in `OUT_DIR`, returning the full path.
+    pub fn write_to_out_dir(&self, file_name: &str) -> Result<PathBuf, Error> {
+        let out_dir = env::var_os("OUT_DIR").ok_or_else(|| {
+            Error::CodegenError("OUT_DIR isn't set, is this being run from build.rs?".to_string())
+        })?;
+
+        let path = PathBuf::from(out_dir).join(file_name);
+        self.write_to(&path)?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::parse;
+
+    use super::{field_ident, struct_name, Generator, StructOptions};
+    use crate::{
+        read_migrations,
+        schema::{get_schemas_from_migrations, schemas_from_definitions},
+        Error,
+    };
+
+    #[tokio::test]
+    async fn test_generate_example_migrations() -> Result<(), Error> {
+        let migs = read_migrations("../blackbird/examples/migrations")?;
+        let generated = Generator::new(get_schemas_from_migrations(migs).await?).generate()?;
+
+        syn::parse_file(&generated).unwrap();
+        assert!(generated.contains("pub struct Person {"));
+        assert!(generated.contains("pub username: String,"));
+        assert!(generated.contains("pub struct PersonId(pub ::surrealdb::sql::Id);"));
+        assert!(generated.contains("pub enum Table {"));
+        assert!(generated.contains("pub mod person {"));
+        assert!(generated.contains("pub const name: ::blackbird_core::select::FieldRef<String>"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generate_with_options() -> Result<(), Error> {
+        let migs = read_migrations("../blackbird/examples/migrations")?;
+        let schemas = get_schemas_from_migrations(migs).await?;
+
+        let mut options = StructOptions::default().derive("Debug, Clone")?;
+        options.field_attrs.insert(
+            "name".to_string(),
+            vec![syn::parse_quote!(#[doc = "Display name"])],
+        );
+
+        let generated = Generator::new(schemas.clone())
+            .with_options("person", options.clone())
+            .generate()?;
+
+        assert!(generated.contains("#[derive(Debug, Clone)]\npub struct Person {"));
+        assert!(generated.contains("///Display name\n    pub name: Option<String>,"));
+
+        options.field_attrs.insert("email".to_string(), Vec::new());
+        let res = Generator::new(schemas)
+            .with_options("person", options)
+            .generate();
+
+        assert!(matches!(res, Err(Error::UnknownField { field, .. }) if field == "email"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_patch_with_default_derived() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse("DEFINE TABLE person SCHEMAFULL; DEFINE FIELD name ON person TYPE string;")?
+                .0
+                 .0,
+        )?;
+
+        let generated = Generator::new(schemas)
+            .with_options(
+                "person",
+                StructOptions::default().derive("Debug, Default, Clone")?,
+            )
+            .generate()?;
+
+        assert!(generated.contains("#[derive(Debug, Default, Clone)]\npub struct Person {"));
+        assert!(generated.contains(
+            "#[derive(Debug, Clone)]\n#[derive(Default)]\n#[allow(dead_code)]\npub struct PersonPatch {"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_untyped_field_with_override() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse("DEFINE TABLE person SCHEMAFULL; DEFINE FIELD email ON person;")?
+                .0
+                 .0,
+        )?;
+
+        let generated = Generator::new(schemas.clone())
+            .with_options(
+                "person",
+                StructOptions::default().override_type("email", "crate::Email")?,
+            )
+            .generate()?;
+
+        assert!(generated.contains("pub email: Option<crate::Email>,"));
+
+        let generated = Generator::new(schemas)
+            .with_options(
+                "person",
+                StructOptions::default().override_type("email", "super::Email")?,
+            )
+            .generate()?;
+
+        assert!(generated.contains("pub email: Option<super::Email>,"));
+        assert!(generated.contains("type PersonEmailOverride = super::Email;"));
+        assert!(generated.contains(
+            "const email: ::blackbird_core::select::FieldRef<super::PersonEmailOverride>"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_ident() -> Result<(), Error> {
+        assert_eq!(field_ident("name")?.to_string(), "name");
+        assert_eq!(field_ident("type")?.to_string(), "r#type");
+        assert_eq!(field_ident("self")?.to_string(), "self_");
+        assert_eq!(field_ident("first-name")?.to_string(), "first_name");
+        assert_eq!(field_ident("lastName")?.to_string(), "last_name");
+        assert_eq!(field_ident("last Name")?.to_string(), "last_name");
+        assert_eq!(field_ident("address.city")?.to_string(), "address_city");
+        assert_eq!(field_ident("2fa")?.to_string(), "_2fa");
+        assert_eq!(field_ident("größe")?.to_string(), "größe");
+        assert_eq!(field_ident("Größe-Wert")?.to_string(), "größe_wert");
+        assert!(matches!(
+            field_ident("💥"),
+            Err(Error::InvalidIdentifier(_))
+        ));
+
+        assert_eq!(struct_name("blog-post")?.to_string(), "BlogPost");
+        assert_eq!(struct_name("self")?.to_string(), "Self_");
+        assert_eq!(struct_name("größe")?.to_string(), "Größe");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_sanitized_field_names() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD type ON person TYPE string;
+                DEFINE FIELD `first-name` ON person TYPE string;
+                DEFINE FIELD lastName ON person TYPE string;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        assert!(generated.contains("pub r#type: Option<String>,"));
+        assert!(generated.contains("/// Stored as `lastName`\n    pub last_name: Option<String>,"));
+        assert!(
+            generated.contains("/// Stored as `first-name`\n    pub first_name: Option<String>,")
+        );
+        assert!(generated.contains(r#"&["first-name"]"#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_reads_nested_fields_first() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE place SCHEMAFULL;
+                DEFINE FIELD address ON place TYPE object;
+                DEFINE FIELD address.city ON place TYPE string ASSERT $value != NONE;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        let city = generated
+            .find(r#"get_path(&obj, &["address", "city"])"#)
+            .unwrap();
+        let address = generated
+            .find(r#"take_path(&mut obj, &["address"])"#)
+            .unwrap();
+        assert!(city < address);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_field_name_collision() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD first_name ON person TYPE string;
+                DEFINE FIELD `first-name` ON person TYPE string;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let res = Generator::new(schemas).generate();
+
+        assert!(matches!(
+            res,
+            Err(Error::FieldNameCollision { ident, .. }) if ident == "first_name"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_enum_for_allowed_values() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE post SCHEMAFULL;
+                DEFINE FIELD status ON post TYPE string ASSERT $value INSIDE ['draft', 'published'];",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        assert!(generated.contains("pub status: PostStatus,"));
+        assert!(generated.contains("pub enum PostStatus {\n    Draft,\n    Published,\n}"));
+        assert!(generated.contains(r#"PostStatus::Published => "published","#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_string_for_unnamable_allowed_values() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE post SCHEMAFULL;
+                DEFINE FIELD status ON post TYPE string ASSERT $value INSIDE ['draft', ''];
+                DEFINE FIELD mood ON post TYPE string ASSERT $value INSIDE ['happy', '💥'];",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        assert!(generated.contains("pub status: String,"));
+        assert!(generated.contains("pub mood: String,"));
+        assert!(!generated.contains("enum PostStatus"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_generate_validate() -> Result<(), Error> {
+        let migs = read_migrations("../blackbird/examples/migrations")?;
+        let generated = Generator::new(get_schemas_from_migrations(migs).await?).generate()?;
+
+        assert!(generated.contains("pub fn validate(&self)"));
+        assert!(generated.contains("\"username\","));
+        assert!(generated.contains("::blackbird_core::validate::Comparison::NotEqual,"));
+        assert!(generated.contains("::blackbird_core::validate::Literal::None,"));
+        assert!(generated.contains("CONSTRAINTS.get_or_init("));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_defaulted_and_computed_fields() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD role ON person TYPE string VALUE $value OR 'member';
+                DEFINE FIELD created ON person TYPE datetime VALUE time::now();",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        assert!(generated.contains("/// Defaults to `'member'`\n    pub role: String,"));
+        assert!(generated.contains("pub created: ::surrealdb::sql::Datetime,"));
+        assert!(generated.contains(r#"&["role"]"#));
+        assert_eq!(generated.matches(r#"&["created"]"#).count(), 1);
+        assert!(generated.contains(
+            "pub struct NewPerson {\n    /// Defaults to `'member'`\n    pub role: Option<String>,\n}"
+        ));
+        assert!(generated.contains(
+            "pub struct PersonPatch {\n    /// Defaults to `'member'`\n    pub role: Option<String>,\n}"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_edge() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE TABLE post SCHEMAFULL;
+                DEFINE TABLE likes SCHEMAFULL;
+                DEFINE FIELD in ON likes TYPE record(person);
+                DEFINE FIELD out ON likes TYPE record(post);
+                DEFINE FIELD weight ON likes TYPE int;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        syn::parse_file(&generated).unwrap();
+        assert!(generated.contains(
+            "pub struct NewLikes {\n    pub r#in: PersonId,\n    pub out: PostId,\n    pub weight: Option<i64>,\n}"
+        ));
+        assert!(generated.contains("pub fn relate_statement(&self)"));
+        assert!(
+            generated.contains("pub struct LikesPatch {\n    pub weight: Option<Option<i64>>,\n}")
+        );
+        assert_eq!(
+            generated.matches("pub fn insert_statement(&self)").count(),
+            2
+        );
+        assert!(generated
+            .contains("pub struct LikesFromPerson(pub ::blackbird_core::graph::Traversal);"));
+        assert!(generated.contains("pub fn likes(&self) -> LikesFromPerson {"));
+        assert!(generated.contains("pub async fn posts("));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_schemaless() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE event SCHEMALESS;
+                DEFINE FIELD kind ON event TYPE string ASSERT $value != NONE;
+                DEFINE FIELD payload ON event;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        syn::parse_file(&generated).unwrap();
+        assert!(generated.contains("pub payload: Option<::surrealdb::sql::Value>,"));
+        assert_eq!(
+            generated
+                .matches(
+                    "pub extra: ::std::collections::BTreeMap<String, ::surrealdb::sql::Value>,"
+                )
+                .count(),
+            3
+        );
+        assert!(generated.contains("extra: obj.0,"));
+
+        let schemas = schemas_from_definitions(
+            parse("DEFINE TABLE event SCHEMALESS; DEFINE FIELD extra ON event TYPE object;")?
+                .0
+                 .0,
+        )?;
+        let res = Generator::new(schemas).generate();
+
+        assert!(matches!(
+            res,
+            Err(Error::FieldNameCollision { ident, .. }) if ident == "extra"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_view() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD age ON person TYPE int ASSERT $value != NONE;
+                DEFINE TABLE people_by_age AS SELECT count() AS total, age FROM person GROUP BY age;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        syn::parse_file(&generated).unwrap();
+        assert!(generated.contains(
+            "pub struct PeopleByAge {\n    pub id: PeopleByAgeId,\n    pub total: Option<i64>,\n    pub age: i64,\n}"
+        ));
+        assert!(!generated.contains("NewPeopleByAge"));
+        assert!(!generated.contains("PeopleByAgePatch"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_view_with_unknown_fields() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD name ON person TYPE string;
+                DEFINE TABLE post SCHEMAFULL;
+                DEFINE FIELD title ON post TYPE string;
+                DEFINE TABLE everything AS SELECT * FROM person, post;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        syn::parse_file(&generated).unwrap();
+        assert!(generated.contains("pub struct Person {"));
+        assert!(generated.contains(
+            "pub struct Everything {\n    pub id: EverythingId,\n    /// Every field of the record, \
+             since the view's fields couldn't be worked out\n    pub extra: ::std::collections::BTreeMap<"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_unique_index_functions() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD username ON person TYPE string ASSERT $value != NONE;
+                DEFINE FIELD email ON person TYPE string;
+                DEFINE FIELD name ON person TYPE string ASSERT $value != NONE;
+                DEFINE INDEX person_username ON person FIELDS username UNIQUE;
+                DEFINE INDEX person_email ON person FIELDS email UNIQUE;
+                DEFINE INDEX person_name ON person FIELDS name;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let generated = Generator::new(schemas).generate()?;
+
+        assert!(generated.contains("pub async fn list("));
+        assert!(generated.contains("pub async fn list_by_username("));
+        assert!(!generated.contains("pub async fn list_by_email("));
+        assert!(!generated.contains("pub async fn list_by_name("));
+        assert!(generated.contains("pub async fn insert_many("));
+        assert!(generated.contains("pub async fn upsert_many("));
+        assert!(generated.contains("pub async fn upsert_many_by_username("));
+        assert!(!generated.contains("pub async fn upsert_many_by_email("));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_untyped_field() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse("DEFINE TABLE person SCHEMAFULL; DEFINE FIELD name ON person;")?
+                .0
+                 .0,
+        )?;
+
+        let res = Generator::new(schemas).generate();
+
+        assert!(matches!(res, Err(Error::UntypedField { field, .. }) if field == "name"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use surrealdb::sql::{Datetime, Duration, Geometry, Number, Object, Thing, Value};
+
+use super::Error;
+
+/// Conversion from a [`Value`] returned by the database into a rust type.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, Error>;
+}
+
+/// Conversion from a rust type into a [`Value`] we can send to the database.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+fn unexpected<T>(expected: &str, value: Value) -> Result<T, Error> {
+    Err(Error::UnexpectedType(
+        expected.to_string(),
+        format!("{:?}", value),
+    ))
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        Ok(value)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        if value.is_true() {
+            Ok(true)
+        } else if value.is_false() {
+            Ok(false)
+        } else {
+            unexpected("bool", value)
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Number(Number::Int(i)) => Ok(i),
+            v => unexpected("int", v),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Number(n) => Ok(n.as_float()),
+            v => unexpected("float", v),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Strand(s) => Ok(s.0),
+            v => unexpected("string", v),
+        }
+    }
+}
+
+macro_rules! from_value_variant {
+    ($typ:ty, $variant:ident, $expected:literal) => {
+        impl FromValue for $typ {
+            fn from_value(value: Value) -> Result<Self, Error> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    v => unexpected($expected, v),
+                }
+            }
+        }
+    };
+}
+
+from_value_variant!(Number, Number, "number");
+from_value_variant!(Datetime, Datetime, "datetime");
+from_value_variant!(Duration, Duration, "duration");
+from_value_variant!(Object, Object, "object");
+from_value_variant!(Thing, Thing, "record");
+from_value_variant!(Geometry, Geometry, "geometry");
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        if value.is_none() || value.is_null() {
+            Ok(None)
+        } else {
+            T::from_value(value).map(Some)
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Array(a) => a.0.into_iter().map(T::from_value).collect(),
+            v => unexpected("array", v),
+        }
+    }
+}
+
+macro_rules! to_value_via_from {
+    ($($typ:ty),*) => {
+        $(
+            impl ToValue for $typ {
+                fn to_value(&self) -> Value {
+                    Value::from(self.clone())
+                }
+            }
+        )*
+    };
+}
+
+to_value_via_from!(bool, i64, f64, String, Number, Datetime, Duration, Object, Thing, Geometry);
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(v) => v.to_value(),
+            None => Value::None,
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::Array(
+            self.iter()
+                .map(ToValue::to_value)
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+}
+
+/// Removes the value at `path` from a (possibly nested) object, leaving any siblings in place.
+/// Missing keys are treated as `NONE`.
+pub fn take_path(obj: &mut Object, path: &[&str]) -> Value {
+    match path {
+        [] => Value::None,
+        [key] => obj.0.remove(*key).unwrap_or(Value::None),
+        [key, rest @ ..] => match obj.0.get_mut(*key) {
+            Some(Value::Object(inner)) => take_path(inner, rest),
+            _ => Value::None,
+        },
+    }
+}
+
+/// Like [`take_path`], but copies the value and leaves it in place, for fields nested inside
+/// another field that still needs to be read in full.
+pub fn get_path(obj: &Object, path: &[&str]) -> Value {
+    match path {
+        [] => Value::None,
+        [key] => obj.0.get(*key).cloned().unwrap_or(Value::None),
+        [key, rest @ ..] => match obj.0.get(*key) {
+            Some(Value::Object(inner)) => get_path(inner, rest),
+            _ => Value::None,
+        },
+    }
+}
+
+/// Sets the value at `path` in a (possibly nested) object, creating any objects along the way.
+/// `NONE` values are left out altogether.
+pub fn insert_path(obj: &mut Object, path: &[&str], value: Value) {
+    if !value.is_none() {
+        set_path(obj, path, value);
+    }
+}
+
+/// Like [`insert_path`], but `NONE`s are kept, so a `MERGE` can clear a field.
+pub fn set_path(obj: &mut Object, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [key] => {
+            obj.0.insert(key.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let inner = obj
+                .0
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Object::default()));
+
+            if let Value::Object(inner) = inner {
+                set_path(inner, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use surrealdb::sql::{Object, Value};
+
+    use super::{get_path, insert_path, take_path, FromValue};
+    use crate::Error;
+
+    #[test]
+    fn test_take_path_nested() -> Result<(), Error> {
+        let mut address = BTreeMap::new();
+        address.insert("city".to_string(), Value::Strand("Wellington".into()));
+        address.insert("zip".to_string(), Value::Strand("6011".into()));
+        let mut obj = BTreeMap::new();
+        obj.insert("address".to_string(), Value::Object(Object(address)));
+        let mut obj = Object(obj);
+
+        let city = String::from_value(take_path(&mut obj, &["address", "city"]))?;
+        let zip = String::from_value(take_path(&mut obj, &["address", "zip"]))?;
+
+        assert_eq!(city, "Wellington");
+        assert_eq!(zip, "6011");
+        assert!(take_path(&mut obj, &["name"]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_path_leaves_value() -> Result<(), Error> {
+        let mut obj = Object::default();
+        insert_path(
+            &mut obj,
+            &["address", "city"],
+            Value::Strand("Wellington".into()),
+        );
+
+        let city = String::from_value(get_path(&obj, &["address", "city"]))?;
+        let address = Object::from_value(take_path(&mut obj, &["address"]))?;
+
+        assert_eq!(city, "Wellington");
+        assert_eq!(address.0.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_option_from_none() -> Result<(), Error> {
+        assert_eq!(Option::<i64>::from_value(Value::None)?, None);
+        assert_eq!(Option::<i64>::from_value(Value::from(3i64))?, Some(3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_path_nested() -> Result<(), Error> {
+        let mut obj = Object::default();
+        insert_path(
+            &mut obj,
+            &["address", "city"],
+            Value::Strand("Wellington".into()),
+        );
+        insert_path(&mut obj, &["address", "zip"], Value::None);
+
+        assert_eq!(
+            String::from_value(take_path(&mut obj, &["address", "city"]))?,
+            "Wellington"
+        );
+        assert!(take_path(&mut obj, &["address", "zip"]).is_none());
+
+        Ok(())
+    }
+}
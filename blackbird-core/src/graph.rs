@@ -0,0 +1,83 @@
+use surrealdb::{
+    sql::{Statement, Table, Thing},
+    Datastore, Session,
+};
+
+use super::{convert::FromValue, parse_single_statement, run_single_statement, Error};
+
+/// The edges of one table leading out of a record, like `person:bob->likes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Traversal {
+    from: Thing,
+    edge: Table,
+}
+
+impl Traversal {
+    pub fn new(from: Thing, edge: &str) -> Self {
+        Self {
+            from,
+            edge: Table::from(edge),
+        }
+    }
+
+    /// `SELECT * FROM person:bob->likes`
+    pub fn edges_statement(&self) -> Result<Statement, Error> {
+        parse_single_statement(&format!("SELECT * FROM {}->{}", self.from, self.edge))
+    }
+
+    /// `SELECT * FROM person:bob->likes->post`
+    pub fn targets_statement(&self, table: &str) -> Result<Statement, Error> {
+        parse_single_statement(&format!(
+            "SELECT * FROM {}->{}->{}",
+            self.from,
+            self.edge,
+            Table::from(table)
+        ))
+    }
+
+    pub async fn fetch_edges<E: FromValue>(
+        &self,
+        ds: &Datastore,
+        sess: &Session,
+    ) -> Result<Vec<E>, Error> {
+        let result = run_single_statement(ds, sess, self.edges_statement()?, None).await?;
+
+        Vec::<E>::from_value(result)
+    }
+
+    /// Fetches the records in `table` at the other end of the edges.
+    pub async fn fetch_targets<T: FromValue>(
+        &self,
+        ds: &Datastore,
+        sess: &Session,
+        table: &str,
+    ) -> Result<Vec<T>, Error> {
+        let result = run_single_statement(ds, sess, self.targets_statement(table)?, None).await?;
+
+        Vec::<T>::from_value(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::Thing;
+
+    use super::Traversal;
+    use crate::Error;
+
+    #[test]
+    fn test_traversal_statements() -> Result<(), Error> {
+        let traversal = Traversal::new(Thing::from(("person", "bob")), "likes");
+
+        assert_eq!(
+            traversal.edges_statement()?.to_string(),
+            "SELECT * FROM person:bob->likes"
+        );
+        assert_eq!(
+            traversal.targets_statement("post")?.to_string(),
+            "SELECT * FROM person:bob->likes->post"
+        );
+
+        Ok(())
+    }
+}
@@ -13,7 +13,18 @@ use surrealdb::{
     Datastore, Session,
 };
 
+pub mod bulk;
+pub mod cache;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+pub mod convert;
+pub mod graph;
+pub mod permissions;
+pub mod query;
 pub mod schema;
+pub mod select;
+pub mod snapshot;
+pub mod validate;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -29,6 +40,50 @@ pub enum Error {
     #[error("Object is missing expected key: {0}")]
     MissingExpectedKey(String),
 
+    #[error("Table {0} is not defined in the migrations")]
+    UnknownTable(String),
+
+    #[error("Field {field} is not defined on table {table}")]
+    UnknownField { table: String, field: String },
+
+    #[error("Column {0} appears more than once in the query output")]
+    DuplicateColumn(String),
+
+    #[error("Snapshot {0} is out of date with the migrations")]
+    StaleSnapshot(String),
+
+    #[error("{0}")]
+    JsonError(#[from] serde_json::Error),
+
+    #[error("Field {field} on table {table} must have a defined type")]
+    UntypedField { table: String, field: String },
+
+    #[error("Table {0} would clash with the generated {1} type")]
+    NameClash(String, String),
+
+    #[error("{0}")]
+    ValidationError(#[from] validate::ValidationErrors),
+
+    #[error("Could not make a rust identifier from {0}")]
+    InvalidIdentifier(String),
+
+    #[error("Fields {first} and {second} in {context} would both be named {ident}")]
+    FieldNameCollision {
+        context: String,
+        first: String,
+        second: String,
+        ident: String,
+    },
+
+    #[error("More than one row has {field} = {value}")]
+    DuplicateKey { field: String, value: String },
+
+    #[error("Pages need to hold at least one row")]
+    EmptyPage,
+
+    #[error("Could not generate code: {0}")]
+    CodegenError(String),
+
     #[error("IO error: {context}")]
     IOError {
         context: String,
@@ -61,6 +116,16 @@ impl<T> Context<T, std::io::Error> for std::io::Result<T> {
     }
 }
 
+/// Parses `sql`, which should be exactly one statement.
+pub(crate) fn parse_single_statement(sql: &str) -> Result<Statement, Error> {
+    let mut statements = parse(sql)?.0 .0;
+
+    match statements.len() {
+        1 => Ok(statements.remove(0)),
+        n => Err(Error::UnexpectedResultCount(1, n)),
+    }
+}
+
 pub async fn run_single_statement(
     ds: &Datastore,
     sess: &Session,
@@ -144,23 +209,70 @@ where
     Ok(entries)
 }
 
-// TODO: make a "migration" struct that wraps a set of statements, and has an optional path so we can point to the source of errors
-pub fn read_migrations<P>(directory: P) -> Result<Vec<Statement>, Error>
+/// A migration file as read from disk, before it's been parsed.
+#[derive(Debug, Clone)]
+pub struct MigrationFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+pub fn read_migration_files<P>(directory: P) -> Result<Vec<MigrationFile>, Error>
 where
     P: AsRef<Path>,
 {
+    get_migration_files(directory)?
+        .into_iter()
+        .map(|path| {
+            let contents = fs::read_to_string(path.as_path())
+                .with_context(|| format!("could not read file {}", path.to_string_lossy()))?;
+            Ok(MigrationFile { path, contents })
+        })
+        .collect()
+}
+
+pub fn parse_migration_files(files: &[MigrationFile]) -> Result<Vec<Statement>, Error> {
     let mut migrations = Vec::default();
 
-    for f in get_migration_files(directory)? {
-        let sql_str = fs::read_to_string(f.as_path())
-            .with_context(|| format!("could not read file {}", f.to_string_lossy()))?;
-        let parsed_ast = parse(&sql_str)?;
+    for f in files {
+        let parsed_ast = parse(&f.contents)?;
         migrations.extend(parsed_ast.0 .0);
     }
 
     Ok(migrations)
 }
 
+/// A hash of the names and contents of a set of migrations.
+///
+/// This is FNV-1a rather than std's `DefaultHasher`, so the result is stable across rust versions.
+pub fn hash_migration_files(files: &[MigrationFile]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    // Stops ("ab", "c") and ("a", "bc") from hashing the same
+    let separator: &[u8] = &[0];
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for f in files {
+        let name = f.path.file_name().unwrap_or_default().to_string_lossy();
+        for bytes in [name.as_bytes(), separator, f.contents.as_bytes(), separator] {
+            for b in bytes {
+                hash ^= u64::from(*b);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+    }
+
+    hash
+}
+
+// TODO: make a "migration" struct that wraps a set of statements, and has an optional path so we can point to the source of errors
+pub fn read_migrations<P>(directory: P) -> Result<Vec<Statement>, Error>
+where
+    P: AsRef<Path>,
+{
+    parse_migration_files(&read_migration_files(directory)?)
+}
+
 pub async fn create_db_and_ns(
     datastore: &Datastore,
     namespace: &str,
@@ -191,7 +303,7 @@ pub async fn create_db_and_ns(
 
 #[cfg(test)]
 mod tests {
-    use crate::get_migration_files;
+    use crate::{get_migration_files, hash_migration_files, read_migration_files, Error};
 
     #[test]
     fn test_get_migration_files() {
@@ -204,4 +316,19 @@ mod tests {
             .to_string_lossy()
             .ends_with("3_add_not_null_col.sql"));
     }
+
+    #[test]
+    fn test_hash_migration_files_changes_with_contents() -> Result<(), Error> {
+        let mut files = read_migration_files("../blackbird/examples/migrations")?;
+        let original = hash_migration_files(&files);
+
+        assert_eq!(original, hash_migration_files(&files));
+
+        files[2]
+            .contents
+            .push_str("DEFINE FIELD age ON person TYPE int;");
+        assert_ne!(original, hash_migration_files(&files));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,184 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+use surrealdb::sql::{Permission, Permissions};
+
+use super::{
+    schema::{field_name, TableSchema},
+    Error,
+};
+
+/// Who can perform an operation, as given in a `PERMISSIONS` clause. These only restrict scope
+/// users, root, namespace and database users can always do everything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    None,
+    Full,
+    /// Allowed for records matching the condition
+    Where(String),
+}
+
+impl From<&Permission> for Access {
+    fn from(permission: &Permission) -> Self {
+        match permission {
+            Permission::None => Access::None,
+            Permission::Full => Access::Full,
+            Permission::Specific(cond) => Access::Where(cond.to_string()),
+        }
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::None => f.write_str("NONE"),
+            Access::Full => f.write_str("FULL"),
+            Access::Where(cond) => write!(f, "WHERE {cond}"),
+        }
+    }
+}
+
+/// The access to each operation on a table or field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OperationAccess {
+    pub select: Access,
+    pub create: Access,
+    pub update: Access,
+    /// For a field, whether it can be cleared when its record is deleted
+    pub delete: Access,
+}
+
+impl From<&Permissions> for OperationAccess {
+    fn from(permissions: &Permissions) -> Self {
+        OperationAccess {
+            select: (&permissions.select).into(),
+            create: (&permissions.create).into(),
+            update: (&permissions.update).into(),
+            delete: (&permissions.delete).into(),
+        }
+    }
+}
+
+impl Display for OperationAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "    select  {}", self.select)?;
+        writeln!(f, "    create  {}", self.create)?;
+        writeln!(f, "    update  {}", self.update)?;
+        writeln!(f, "    delete  {}", self.delete)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldAccess {
+    pub field: String,
+    pub access: OperationAccess,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TableAccess {
+    pub table: String,
+    pub access: OperationAccess,
+    pub fields: Vec<FieldAccess>,
+}
+
+impl TableAccess {
+    pub fn for_schema(schema: &TableSchema) -> TableAccess {
+        TableAccess {
+            table: schema.name.clone(),
+            access: schema.permissions(),
+            fields: schema
+                .fields
+                .iter()
+                .map(|f| FieldAccess {
+                    field: field_name(f),
+                    access: (&f.permissions).into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Who can do what to every table and field, for auditing the permissions the migrations set up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessReport {
+    pub tables: Vec<TableAccess>,
+}
+
+impl AccessReport {
+    pub fn new(schemas: &[TableSchema]) -> AccessReport {
+        AccessReport {
+            tables: schemas.iter().map(TableAccess::for_schema).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl Display for AccessReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for table in &self.tables {
+            writeln!(f, "table {}", table.table)?;
+            write!(f, "{}", table.access)?;
+
+            for field in &table.fields {
+                writeln!(f, "field {}.{}", table.table, field.field)?;
+                write!(f, "{}", field.access)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::parse;
+
+    use super::{Access, AccessReport};
+    use crate::{schema::schemas_from_definitions, Error};
+
+    #[test]
+    fn test_access_report() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE post SCHEMAFULL
+                    PERMISSIONS FOR select FULL, FOR create, update WHERE author = $auth.id, FOR delete NONE;
+                DEFINE FIELD secret ON post TYPE string
+                    PERMISSIONS FOR select NONE, FOR delete WHERE author = $auth.id;",
+            )?
+            .0
+             .0,
+        )?;
+
+        let report = AccessReport::new(&schemas);
+        let post = &report.tables[0];
+
+        assert_eq!(post.access.select, Access::Full);
+        assert_eq!(
+            post.access.update,
+            Access::Where("author = $auth.id".to_string())
+        );
+        assert_eq!(post.access.delete, Access::None);
+        assert_eq!(post.fields[0].field, "secret");
+        assert_eq!(post.fields[0].access.select, Access::None);
+        assert_eq!(
+            post.fields[0].access.delete,
+            Access::Where("author = $auth.id".to_string())
+        );
+
+        let text = report.to_string();
+        assert!(
+            text.starts_with("table post\n    select  FULL\n    create  WHERE author = $auth.id\n")
+        );
+        assert!(text.contains("field post.secret\n    select  NONE\n"));
+        assert!(text.ends_with("    delete  WHERE author = $auth.id\n"));
+        assert!(report
+            .to_json()?
+            .contains(r#""where": "author = $auth.id""#));
+
+        Ok(())
+    }
+}
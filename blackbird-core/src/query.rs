@@ -0,0 +1,725 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use surrealdb::{
+    sql::{
+        parse, statements::SelectStatement, Data, Field, Fields, Function, Idiom, Kind, Part,
+        Query, Statement, Subquery, Value,
+    },
+    Datastore, Session,
+};
+
+use super::{
+    convert::FromValue,
+    run_statements,
+    schema::{is_nullable, TableSchema},
+    Error,
+};
+
+/// Checks that every table and field referenced by `query` is defined in `schemas`.
+pub fn validate_query(query: &Query, schemas: &[TableSchema]) -> Result<(), Error> {
+    for statement in query.0 .0.iter() {
+        validate_statement(statement, schemas)?;
+    }
+
+    Ok(())
+}
+
+fn validate_statement(statement: &Statement, schemas: &[TableSchema]) -> Result<(), Error> {
+    match statement {
+        Statement::Select(s) => validate_select(s, schemas),
+        Statement::Create(s) => {
+            let tables = resolve_tables(&s.what.0, schemas)?;
+            if let Some(data) = s.data.as_ref() {
+                validate_data(data, tables.as_deref(), schemas)?;
+            }
+            Ok(())
+        }
+        Statement::Update(s) => {
+            let tables = resolve_tables(&s.what.0, schemas)?;
+            if let Some(data) = s.data.as_ref() {
+                validate_data(data, tables.as_deref(), schemas)?;
+            }
+            if let Some(cond) = s.cond.as_ref() {
+                validate_value(&cond.0, tables.as_deref(), schemas)?;
+            }
+            Ok(())
+        }
+        Statement::Delete(s) => {
+            let tables = resolve_tables(&s.what.0, schemas)?;
+            if let Some(cond) = s.cond.as_ref() {
+                validate_value(&cond.0, tables.as_deref(), schemas)?;
+            }
+            Ok(())
+        }
+        Statement::Insert(s) => {
+            let table = find_table(&s.into.0, schemas)?;
+            validate_data(&s.data, Some(std::slice::from_ref(&table)), schemas)
+        }
+        Statement::Relate(s) => find_table(&s.kind.0, schemas).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+fn validate_select(select: &SelectStatement, schemas: &[TableSchema]) -> Result<(), Error> {
+    let tables = resolve_tables(&select.what.0, schemas)?;
+    let tables = tables.as_deref();
+
+    let mut aliases = Vec::new();
+    for field in select.expr.0.iter() {
+        match field {
+            Field::All => {}
+            Field::Alone(v) => validate_value(v, tables, schemas)?,
+            Field::Alias(v, alias) => {
+                validate_value(v, tables, schemas)?;
+                aliases.extend(field_path(alias).first().copied());
+            }
+        }
+    }
+
+    if let Some(cond) = select.cond.as_ref() {
+        validate_value(&cond.0, tables, schemas)?;
+    }
+
+    // These clauses operate on the projected output, so they may also refer to an alias
+    let output_idioms = select
+        .split
+        .iter()
+        .flat_map(|s| s.0.iter().map(|s| &s.0))
+        .chain(select.group.iter().flat_map(|g| g.0.iter().map(|g| &g.0)))
+        .chain(
+            select
+                .order
+                .iter()
+                .flat_map(|o| o.0.iter().map(|o| &o.order)),
+        )
+        .chain(select.fetch.iter().flat_map(|f| f.0.iter().map(|f| &f.0)));
+
+    for idiom in output_idioms {
+        let is_alias = field_path(idiom)
+            .first()
+            .map(|first| aliases.contains(first))
+            .unwrap_or_default();
+
+        if !is_alias {
+            validate_idiom(idiom, tables, schemas)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_data(
+    data: &Data,
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    match data {
+        Data::SetExpression(sets) => {
+            for (idiom, _, value) in sets {
+                validate_idiom(idiom, tables, schemas)?;
+                validate_value(value, tables, schemas)?;
+            }
+            Ok(())
+        }
+        Data::ContentExpression(v) | Data::MergeExpression(v) | Data::SingleExpression(v) => {
+            validate_object_keys(v, tables, schemas)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_object_keys(
+    value: &Value,
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    match value {
+        Value::Object(o) => {
+            for key in o.0.keys() {
+                validate_path(&[key.as_str()], tables, schemas)?;
+            }
+            Ok(())
+        }
+        Value::Array(a) => {
+            a.0.iter()
+                .try_for_each(|v| validate_object_keys(v, tables, schemas))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_value(
+    value: &Value,
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    match value {
+        Value::Idiom(i) => validate_idiom(i, tables, schemas),
+        Value::Expression(e) => {
+            validate_value(&e.l, tables, schemas)?;
+            validate_value(&e.r, tables, schemas)
+        }
+        Value::Function(f) => function_args(f)
+            .iter()
+            .try_for_each(|v| validate_value(v, tables, schemas)),
+        Value::Array(a) => {
+            a.0.iter()
+                .try_for_each(|v| validate_value(v, tables, schemas))
+        }
+        Value::Subquery(s) => validate_subquery(s, tables, schemas),
+        _ => Ok(()),
+    }
+}
+
+fn validate_subquery(
+    subquery: &Subquery,
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    match subquery {
+        Subquery::Value(v) => validate_value(v, tables, schemas),
+        Subquery::Select(s) => validate_select(s, schemas),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn function_args(function: &Function) -> &[Value] {
+    match function {
+        Function::Normal(_, args) | Function::Script(_, args) => args,
+        Function::Cast(_, v) => std::slice::from_ref(v),
+    }
+}
+
+fn validate_idiom(
+    idiom: &Idiom,
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    validate_path(&field_path(idiom), tables, schemas)
+}
+
+fn validate_path(
+    path: &[&str],
+    tables: Option<&[&TableSchema]>,
+    schemas: &[TableSchema],
+) -> Result<(), Error> {
+    let tables = match tables {
+        Some(t) => t,
+        // We couldn't work out which tables are being queried (e.g. `FROM $param`)
+        None => return Ok(()),
+    };
+
+    if path.is_empty() || path[0] == "id" {
+        return Ok(());
+    }
+
+    if tables.iter().any(|t| table_has_path(t, path, schemas)) {
+        return Ok(());
+    }
+
+    Err(Error::UnknownField {
+        table: tables
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        field: path.join("."),
+    })
+}
+
+fn table_has_path(table: &TableSchema, path: &[&str], schemas: &[TableSchema]) -> bool {
+    if !table.definition.full {
+        // Schemaless tables can contain anything
+        return true;
+    }
+
+    for field in &table.fields {
+        let defined = field_path(&field.name);
+
+        if defined.len() >= path.len() {
+            if defined[..path.len()] == *path {
+                return true;
+            }
+            continue;
+        }
+
+        if path[..defined.len()] != defined[..] {
+            continue;
+        }
+
+        let rest = &path[defined.len()..];
+        match field.kind.as_ref() {
+            None | Some(Kind::Any) | Some(Kind::Object) => return true,
+            Some(Kind::Record(linked)) if linked.is_empty() => return true,
+            Some(Kind::Record(linked)) => {
+                let found = linked.iter().any(|l| {
+                    schemas
+                        .iter()
+                        .filter(|s| s.name == l.0)
+                        .any(|s| rest[0] == "id" || table_has_path(s, rest, schemas))
+                });
+
+                if found {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// The leading field names of an idiom, e.g. `address.city` for `address.city[0]->lives_in`.
+pub(crate) fn field_path(idiom: &Idiom) -> Vec<&str> {
+    idiom
+        .0
+        .iter()
+        .map_while(|p| match p {
+            Part::Field(ident) => Some(ident.0.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn find_table<'a>(
+    name: &str,
+    schemas: &'a [TableSchema],
+) -> Result<&'a TableSchema, Error> {
+    schemas
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| Error::UnknownTable(name.to_string()))
+}
+
+/// Returns `None` if any of the targets aren't plain tables or records, since we can't check
+/// fields against an unknown table.
+fn resolve_tables<'a>(
+    what: &[Value],
+    schemas: &'a [TableSchema],
+) -> Result<Option<Vec<&'a TableSchema>>, Error> {
+    let mut tables = Vec::with_capacity(what.len());
+    let mut all_known = true;
+
+    for w in what {
+        match w {
+            Value::Table(t) => tables.push(find_table(&t.0, schemas)?),
+            Value::Thing(t) => tables.push(find_table(&t.tb, schemas)?),
+            Value::Subquery(s) => {
+                validate_subquery(s, None, schemas)?;
+                all_known = false;
+            }
+            _ => all_known = false,
+        }
+    }
+
+    Ok(if all_known { Some(tables) } else { None })
+}
+
+/// A column in the output of a `SELECT`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    /// The name to use for the column in a rust struct
+    pub name: String,
+    /// The keys to follow in each returned object to reach the column's value
+    pub path: Vec<String>,
+    pub ty: ColumnType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnType {
+    /// A value of the given kind, `None` if we can't tell what it'll be
+    Value { kind: Option<Kind>, nullable: bool },
+    /// A record link that's been pulled in with `FETCH`
+    Fetched {
+        columns: Vec<Column>,
+        nullable: bool,
+    },
+}
+
+/// Works out the columns returned by a `SELECT`.
+///
+/// Returns `None` when the shape of the output can't be determined, e.g. when selecting from
+/// more than one table or projecting an expression without an alias.
+pub fn infer_select_columns(
+    select: &SelectStatement,
+    schemas: &[TableSchema],
+) -> Result<Option<Vec<Column>>, Error> {
+    let tables = match resolve_tables(&select.what.0, schemas)? {
+        Some(tables) if tables.len() == 1 => tables,
+        _ => return Ok(None),
+    };
+
+    let fetched = select
+        .fetch
+        .iter()
+        .flat_map(|f| f.0.iter().map(|f| field_path(&f.0)))
+        .collect::<Vec<_>>();
+
+    infer_columns(&select.expr, tables[0], schemas, &fetched)
+}
+
+pub(crate) fn infer_columns(
+    fields: &Fields,
+    table: &TableSchema,
+    schemas: &[TableSchema],
+    fetched: &[Vec<&str>],
+) -> Result<Option<Vec<Column>>, Error> {
+    let mut columns: Vec<Column> = Vec::new();
+
+    for field in fields.0.iter() {
+        let mut new_columns = match field {
+            Field::All => all_columns(table, schemas, fetched)?,
+            Field::Alone(Value::Idiom(i)) => {
+                let path = field_path(i);
+                if path.len() != i.0.len() {
+                    // Graph traversals and the like end up keyed by the whole expression
+                    return Ok(None);
+                }
+
+                vec![Column {
+                    name: path.join("_"),
+                    path: path.iter().map(|p| p.to_string()).collect(),
+                    ty: path_type(table, &path, schemas, fetched)?,
+                }]
+            }
+            Field::Alone(_) => return Ok(None),
+            Field::Alias(v, alias) => {
+                let alias = field_path(alias);
+                let ty = match v {
+                    Value::Idiom(i) if field_path(i).len() == i.0.len() => {
+                        path_type(table, &field_path(i), schemas, fetched)?
+                    }
+                    v => expression_type(v),
+                };
+
+                vec![Column {
+                    name: alias.join("_"),
+                    path: alias.iter().map(|p| p.to_string()).collect(),
+                    ty,
+                }]
+            }
+        };
+
+        for c in new_columns.iter() {
+            if columns.iter().any(|existing| existing.name == c.name) {
+                return Err(Error::DuplicateColumn(c.name.clone()));
+            }
+        }
+
+        columns.append(&mut new_columns);
+    }
+
+    Ok(Some(columns))
+}
+
+fn all_columns(
+    table: &TableSchema,
+    schemas: &[TableSchema],
+    fetched: &[Vec<&str>],
+) -> Result<Vec<Column>, Error> {
+    let mut columns = vec![Column {
+        name: "id".to_string(),
+        path: vec!["id".to_string()],
+        ty: id_type(table),
+    }];
+
+    for field in &table.fields {
+        let path = field_path(&field.name);
+        // Nested fields are part of their parent's object
+        if path.len() == 1 {
+            columns.push(Column {
+                name: path[0].to_string(),
+                path: vec![path[0].to_string()],
+                ty: path_type(table, &path, schemas, fetched)?,
+            });
+        }
+    }
+
+    Ok(columns)
+}
+
+fn id_type(table: &TableSchema) -> ColumnType {
+    ColumnType::Value {
+        kind: Some(Kind::Record(vec![table.name.as_str().into()])),
+        nullable: false,
+    }
+}
+
+fn path_type(
+    table: &TableSchema,
+    path: &[&str],
+    schemas: &[TableSchema],
+    fetched: &[Vec<&str>],
+) -> Result<ColumnType, Error> {
+    if path == ["id"] {
+        return Ok(id_type(table));
+    }
+
+    let unknown = ColumnType::Value {
+        kind: None,
+        nullable: true,
+    };
+
+    if !table.definition.full {
+        return Ok(unknown);
+    }
+
+    for field in &table.fields {
+        let defined = field_path(&field.name);
+        let nullable = is_nullable(field);
+
+        if defined == path {
+            let linked = match field.kind.as_ref() {
+                Some(Kind::Record(linked)) if linked.len() == 1 => Some(&linked[0].0),
+                _ => None,
+            };
+
+            return match linked {
+                Some(linked) if fetched.iter().any(|f| f == path) => Ok(ColumnType::Fetched {
+                    columns: all_columns(find_table(linked, schemas)?, schemas, &[])?,
+                    nullable,
+                }),
+                _ => Ok(ColumnType::Value {
+                    kind: field.kind.clone(),
+                    nullable,
+                }),
+            };
+        }
+
+        if defined.len() > path.len() && defined[..path.len()] == *path {
+            return Ok(ColumnType::Value {
+                kind: Some(Kind::Object),
+                nullable: true,
+            });
+        }
+
+        if defined.len() < path.len() && path[..defined.len()] == defined[..] {
+            let rest = &path[defined.len()..];
+            return match field.kind.as_ref() {
+                Some(Kind::Record(linked)) if linked.len() == 1 => {
+                    let linked = find_table(&linked[0].0, schemas)?;
+                    // Following a link to a missing record gives us NONE
+                    Ok(match path_type(linked, rest, schemas, &[])? {
+                        ColumnType::Value { kind, .. } => ColumnType::Value {
+                            kind,
+                            nullable: true,
+                        },
+                        ColumnType::Fetched { columns, .. } => ColumnType::Fetched {
+                            columns,
+                            nullable: true,
+                        },
+                    })
+                }
+                _ => Ok(unknown),
+            };
+        }
+    }
+
+    Err(Error::UnknownField {
+        table: table.name.clone(),
+        field: path.join("."),
+    })
+}
+
+fn expression_type(value: &Value) -> ColumnType {
+    let kind = match value {
+        Value::Function(f) => function_kind(f),
+        _ => None,
+    };
+
+    ColumnType::Value {
+        kind,
+        nullable: true,
+    }
+}
+
+fn function_kind(function: &Function) -> Option<Kind> {
+    match function {
+        Function::Normal(name, _) if name == "count" => Some(Kind::Int),
+        _ => None,
+    }
+}
+
+/// A query that's been checked at compile time, along with the type each returned row is
+/// decoded into.
+pub struct TypedQuery<T> {
+    sql: &'static str,
+    row: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedQuery<T> {
+    pub const fn new(sql: &'static str) -> Self {
+        Self {
+            sql,
+            row: PhantomData,
+        }
+    }
+
+    pub fn sql(&self) -> &'static str {
+        self.sql
+    }
+}
+
+impl<T: FromValue> TypedQuery<T> {
+    /// Runs the query, and decodes the result of the last statement in it.
+    pub async fn fetch(
+        &self,
+        ds: &Datastore,
+        sess: &Session,
+        vars: Option<BTreeMap<String, Value>>,
+    ) -> Result<Vec<T>, Error> {
+        let query = parse(self.sql)?;
+        let results = run_statements(ds, sess, query.0 .0, vars).await;
+
+        let result = results
+            .into_iter()
+            .last()
+            .ok_or_else(|| Error::UnexpectedResultCount(1, 0))??;
+
+        Vec::<T>::from_value(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::{parse, Kind, Statement};
+
+    use super::{infer_select_columns, validate_query, Column, ColumnType};
+    use crate::{read_migrations, schema::get_schemas_from_migrations, Error};
+
+    async fn validate(sql: &str) -> Result<(), Error> {
+        let migs = read_migrations("../blackbird/examples/migrations")?;
+        let schemas = get_schemas_from_migrations(migs).await?;
+
+        validate_query(&parse(sql)?, &schemas)
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_valid_select() -> Result<(), Error> {
+        validate("SELECT name, id FROM person WHERE username = $u ORDER BY name").await
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_unknown_field() {
+        let res = validate("SELECT nmae FROM person WHERE username = $u").await;
+
+        assert!(matches!(res, Err(Error::UnknownField { field, .. }) if field == "nmae"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_unknown_field_in_where() {
+        let res = validate("SELECT name FROM person WHERE usrname = $u").await;
+
+        assert!(matches!(res, Err(Error::UnknownField { field, .. }) if field == "usrname"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_dropped_field() {
+        let res = validate("SELECT age FROM person").await;
+
+        assert!(matches!(res, Err(Error::UnknownField { field, .. }) if field == "age"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_unknown_table() {
+        let res = validate("SELECT name FROM people").await;
+
+        assert!(matches!(res, Err(Error::UnknownTable(t)) if t == "people"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_query_alias_in_order() -> Result<(), Error> {
+        validate("SELECT name AS n FROM person ORDER BY n").await
+    }
+
+    async fn infer(migrations: &str, sql: &str) -> Result<Option<Vec<Column>>, Error> {
+        let schemas = get_schemas_from_migrations(parse(migrations)?.0 .0).await?;
+
+        match parse(sql)?.0 .0.into_iter().next() {
+            Some(Statement::Select(s)) => infer_select_columns(&s, &schemas),
+            s => panic!("Expected a select statement, got {s:?}"),
+        }
+    }
+
+    const LINKED_TABLES: &str = r#"
+        DEFINE TABLE person SCHEMAFULL;
+        DEFINE FIELD name ON person TYPE string ASSERT $value != NONE;
+        DEFINE TABLE post SCHEMAFULL;
+        DEFINE FIELD title ON post TYPE string;
+        DEFINE FIELD author ON post TYPE record(person);
+        DEFINE FIELD meta ON post TYPE object;
+        DEFINE FIELD meta.views ON post TYPE int;
+    "#;
+
+    #[tokio::test]
+    async fn test_infer_select_all() -> Result<(), Error> {
+        let columns = infer(LINKED_TABLES, "SELECT * FROM person").await?.unwrap();
+
+        assert_eq!(
+            columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["id", "name"]
+        );
+        assert_eq!(
+            columns[1].ty,
+            ColumnType::Value {
+                kind: Some(Kind::String),
+                nullable: false
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_infer_select_alias_and_nested() -> Result<(), Error> {
+        let columns = infer(
+            LINKED_TABLES,
+            "SELECT title AS heading, meta.views, author.name FROM post",
+        )
+        .await?
+        .unwrap();
+
+        assert_eq!(columns[0].name, "heading");
+        assert_eq!(columns[1].name, "meta_views");
+        assert_eq!(columns[1].path, vec!["meta", "views"]);
+        assert_eq!(columns[2].name, "author_name");
+        assert_eq!(
+            columns[2].ty,
+            ColumnType::Value {
+                kind: Some(Kind::String),
+                nullable: true
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_infer_select_fetch() -> Result<(), Error> {
+        let columns = infer(LINKED_TABLES, "SELECT author FROM post FETCH author")
+            .await?
+            .unwrap();
+
+        match &columns[0].ty {
+            ColumnType::Fetched { columns, .. } => {
+                assert_eq!(
+                    columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+                    vec!["id", "name"]
+                );
+            }
+            t => panic!("Expected a fetched column, got {t:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_infer_select_unaliased_expression() -> Result<(), Error> {
+        assert!(infer(LINKED_TABLES, "SELECT count() FROM post")
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+}
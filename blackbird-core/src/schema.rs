@@ -3,19 +3,28 @@ use std::fmt::Display;
 use surrealdb::{
     sql::{
         parse,
-        statements::{DefineFieldStatement, DefineStatement, DefineTableStatement, InfoStatement},
-        Object, Operator, Statement, Value,
+        statements::{
+            DefineFieldStatement, DefineIndexStatement, DefineStatement, DefineTableStatement,
+            InfoStatement,
+        },
+        Kind, Object, Operator, Statement, Subquery, Value,
     },
     Datastore, Session,
 };
 
-use super::{apply_migrations_to_in_mem_db, run_single_statement, run_statements, Error};
+use super::{
+    apply_migrations_to_in_mem_db,
+    permissions::OperationAccess,
+    query::{field_path, find_table, function_args, infer_columns, Column},
+    run_single_statement, run_statements, Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct TableSchema {
     pub name: String,
     pub definition: DefineTableStatement,
     pub fields: Vec<DefineFieldStatement>,
+    pub indexes: Vec<DefineIndexStatement>,
 }
 
 impl Display for TableSchema {
@@ -24,12 +33,65 @@ impl Display for TableSchema {
         for field in &self.fields {
             f.write_fmt(format_args!("{field};\n"))?;
         }
+        for index in &self.indexes {
+            f.write_fmt(format_args!("{index};\n"))?;
+        }
 
         Ok(())
     }
 }
 
 impl TableSchema {
+    pub fn field(&self, name: &str) -> Option<&DefineFieldStatement> {
+        self.fields.iter().find(|f| field_name(f) == name)
+    }
+
+    pub fn is_schemaless(&self) -> bool {
+        !self.definition.full
+    }
+
+    /// Whether this is a view, defined `AS SELECT ...`.
+    pub fn is_view(&self) -> bool {
+        self.definition.view.is_some()
+    }
+
+    /// Works out the fields of a view from its projection and the table it selects from.
+    /// `None` if this isn't a view, or its shape can't be determined, e.g. when it selects from
+    /// more than one table.
+    pub fn view_columns(&self, schemas: &[TableSchema]) -> Result<Option<Vec<Column>>, Error> {
+        let view = match self.definition.view.as_ref() {
+            Some(view) => view,
+            None => return Ok(None),
+        };
+
+        match view.what.0.as_slice() {
+            [table] => infer_columns(&view.expr, find_table(&table.0, schemas)?, schemas, &[]),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether a `UNIQUE` index covers `field` and nothing else, so records can be paged through
+    /// in its order.
+    pub fn has_unique_index(&self, field: &str) -> bool {
+        self.indexes
+            .iter()
+            .any(|i| i.uniq && index_fields(i) == [field])
+    }
+
+    /// Whether this is a graph edge, created with `RELATE`, which we take to be any table with
+    /// `in` and `out` fields.
+    pub fn is_edge(&self) -> bool {
+        self.field("in").is_some() && self.field("out").is_some()
+    }
+
+    /// Who can select, create, update and delete records, see [`AccessReport`] for the fields
+    /// too.
+    ///
+    /// [`AccessReport`]: crate::permissions::AccessReport
+    pub fn permissions(&self) -> OperationAccess {
+        (&self.definition.permissions).into()
+    }
+
     fn from_tables_info_object(info_obj: Object) -> Result<Vec<TableSchema>, Error> {
         let tb_val = info_obj
             .0
@@ -62,6 +124,7 @@ impl TableSchema {
             name: name.clone(),
             definition: define_statement,
             fields: Vec::default(),
+            indexes: Vec::default(),
         })
     }
 }
@@ -128,14 +191,10 @@ pub async fn get_schemas(ds: &Datastore, sess: &Session) -> Result<Vec<TableSche
 
 fn set_table_schema(
     schema: &mut TableSchema,
-    fields: Result<Value, surrealdb::Error>,
+    info: Result<Value, surrealdb::Error>,
 ) -> Result<(), Error> {
-    let fields = fields?;
-
-    let fields = match fields {
-        Value::Object(mut o) => o
-            .remove("fd")
-            .ok_or_else(|| Error::MissingExpectedKey("fd".to_string()))?,
+    let mut info = match info? {
+        Value::Object(o) => o,
         t => {
             return Err(Error::UnexpectedType(
                 "Value::Object".to_string(),
@@ -144,25 +203,43 @@ fn set_table_schema(
         }
     };
 
-    let fields = match fields {
-        Value::Object(o) => o
-            .iter()
-            .map(|(_, v)| parse_to_define_statement(v))
-            .map(|s| s.and_then(extract_define_field_from_define))
-            .collect::<Result<Vec<_>, _>>(),
-        t => {
-            return Err(Error::UnexpectedType(
-                "Value::Object".to_string(),
-                format!("{:?}", t),
-            ))
-        }
-    }?;
+    schema.fields = definitions_in(&mut info, "fd")?
+        .into_iter()
+        .map(extract_define_field_from_define)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    schema.fields = fields;
+    schema.indexes = definitions_in(&mut info, "ix")?
+        .into_iter()
+        .map(|s| match s {
+            DefineStatement::Index(i) => Ok(i),
+            t => Err(Error::UnexpectedType(
+                "DefineStatement::Index".to_string(),
+                format!("{:?}", t),
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(())
 }
 
+/// Parses the definitions under `key` in the output of an `INFO FOR TABLE`.
+fn definitions_in(info: &mut Object, key: &str) -> Result<Vec<DefineStatement>, Error> {
+    let defs = info
+        .remove(key)
+        .ok_or_else(|| Error::MissingExpectedKey(key.to_string()))?;
+
+    match defs {
+        Value::Object(o) => o
+            .iter()
+            .map(|(_, v)| parse_to_define_statement(v))
+            .collect(),
+        t => Err(Error::UnexpectedType(
+            "Value::Object".to_string(),
+            format!("{:?}", t),
+        )),
+    }
+}
+
 fn extract_define_field_from_define(
     statement: DefineStatement,
 ) -> Result<DefineFieldStatement, Error> {
@@ -175,20 +252,211 @@ fn extract_define_field_from_define(
     }
 }
 
-pub fn is_nullable(field: &DefineFieldStatement) -> bool {
-    // TODO: this won't detect anything aside from `ASSERT $value != NONE;`.
-    //       Probably need to build a recursive parser that only follows `and`s?
+/// The name of a field as it's stored in the database, with nested fields joined by `.`.
+///
+/// Unlike the `Display` impl on the field's `Idiom`, this doesn't escape names like `first-name`.
+pub fn field_name(field: &DefineFieldStatement) -> String {
+    field_path(&field.name).join(".")
+}
+
+/// The names of the fields an index covers, in order.
+pub fn index_fields(index: &DefineIndexStatement) -> Vec<String> {
+    index
+        .cols
+        .0
+        .iter()
+        .map(|c| field_path(c).join("."))
+        .collect()
+}
 
+pub(crate) fn parenthesized(subquery: &Subquery) -> Option<&Value> {
+    match subquery {
+        Subquery::Value(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// The conditions in a field's `ASSERT` that all have to hold, i.e. the assert split on `AND`s.
+///
+/// SurrealQL doesn't have operator precedence, so `$value != NONE AND $value INSIDE [...]` is
+/// actually `$value != (NONE AND ...)`. Conditions need to be in parentheses to be picked out.
+pub fn assert_conditions(field: &DefineFieldStatement) -> Vec<&Value> {
+    fn flatten<'a>(value: &'a Value, conditions: &mut Vec<&'a Value>) {
+        match value {
+            Value::Expression(e) if e.o == Operator::And => {
+                flatten(&e.l, conditions);
+                flatten(&e.r, conditions);
+            }
+            Value::Subquery(s) => match parenthesized(s) {
+                Some(v) => flatten(v, conditions),
+                None => conditions.push(value),
+            },
+            v => conditions.push(v),
+        }
+    }
+
+    let mut conditions = Vec::new();
     if let Some(assert) = field.assert.as_ref() {
-        match assert {
-            Value::Expression(e) => {
-                return e.o != Operator::NotEqual || (!e.l.is_none() && !e.r.is_none())
+        flatten(assert, &mut conditions);
+    }
+
+    conditions
+}
+
+pub(crate) fn is_value_param(value: &Value) -> bool {
+    matches!(value, Value::Param(p) if p.to_string() == "$value")
+}
+
+/// Whether `value` refers to `$value` anywhere in it.
+///
+/// Subqueries other than plain parenthesized values aren't looked into, and are assumed to use
+/// it, so a field is only ever left out of what the caller writes when it clearly can't matter.
+fn uses_value_param(value: &Value) -> bool {
+    match value {
+        Value::Param(_) => is_value_param(value),
+        Value::Expression(e) => uses_value_param(&e.l) || uses_value_param(&e.r),
+        Value::Function(f) => function_args(f).iter().any(uses_value_param),
+        Value::Array(a) => a.0.iter().any(uses_value_param),
+        Value::Object(o) => o.0.values().any(uses_value_param),
+        Value::Subquery(s) => parenthesized(s).map_or(true, uses_value_param),
+        _ => false,
+    }
+}
+
+/// Where a field's value comes from, based on its `VALUE` clause.
+///
+/// Computed fields are the read only ones: they're left out of the `New` and `Patch` structs,
+/// since anything sent for them is thrown away.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// No `VALUE` clause, or one that transforms what the caller passed, like
+    /// `string::lowercase($value)`. The caller has to provide it.
+    Plain,
+    /// `VALUE $value OR 'x'`, the caller can leave it out to get the default.
+    Defaulted(Value),
+    /// `VALUE time::now()`, always set by the database, whatever the caller passes. Read only.
+    Computed(Value),
+}
+
+pub fn field_value(field: &DefineFieldStatement) -> FieldValue {
+    let value = match field.value.as_ref() {
+        Some(v) => v,
+        None => return FieldValue::Plain,
+    };
+
+    if let Value::Expression(e) = value {
+        if matches!(e.o, Operator::Or | Operator::Nco | Operator::Tco) && is_value_param(&e.l) {
+            return FieldValue::Defaulted(e.r.clone());
+        }
+    }
+
+    if uses_value_param(value) {
+        FieldValue::Plain
+    } else {
+        FieldValue::Computed(value.clone())
+    }
+}
+
+/// The table a record link points to, when it's restricted to exactly one, like `record(person)`.
+pub fn linked_table(kind: &Kind) -> Option<&str> {
+    match kind {
+        Kind::Record(tables) => match tables.as_slice() {
+            [table] => Some(table.0.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn is_nullable(field: &DefineFieldStatement) -> bool {
+    // Whatever's passed in, the database fills these in
+    if field_value(field) != FieldValue::Plain {
+        return false;
+    }
+
+    let not_none = assert_conditions(field).into_iter().any(|c| match c {
+        Value::Expression(e) => e.o == Operator::NotEqual && (e.l.is_none() || e.r.is_none()),
+        _ => false,
+    });
+
+    // NONE isn't inside a list of strings, so those asserts rule it out too
+    !not_none && allowed_values(field).is_none()
+}
+
+/// The closed set of strings a field can hold, from an `ASSERT $value INSIDE ['a', 'b']`.
+pub fn allowed_values(field: &DefineFieldStatement) -> Option<Vec<String>> {
+    assert_conditions(field).into_iter().find_map(|c| match c {
+        Value::Expression(e) if e.o == Operator::Inside && is_value_param(&e.l) => match &e.r {
+            Value::Array(values) if !values.0.is_empty() => values
+                .0
+                .iter()
+                .map(|v| match v {
+                    Value::Strand(s) => Some(s.0.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Builds schemas from `DEFINE TABLE`, `FIELD` and `INDEX` statements that have already been
+/// folded down, like the output of [`definitions`]. Unlike [`get_schemas_from_migrations`] this
+/// doesn't apply anything to a database, so `REMOVE`s and redefinitions aren't handled.
+pub fn schemas_from_definitions(statements: Vec<Statement>) -> Result<Vec<TableSchema>, Error> {
+    let mut schemas: Vec<TableSchema> = Vec::new();
+    let mut fields = Vec::new();
+    let mut indexes = Vec::new();
+
+    for statement in statements {
+        match extract_define_statement(statement)? {
+            DefineStatement::Table(t) => schemas.push(TableSchema {
+                name: t.name.0.clone(),
+                definition: t,
+                fields: Vec::default(),
+                indexes: Vec::default(),
+            }),
+            DefineStatement::Field(f) => fields.push(f),
+            DefineStatement::Index(i) => indexes.push(i),
+            t => {
+                return Err(Error::UnexpectedType(
+                    "DefineStatement::Table, DefineStatement::Field or DefineStatement::Index"
+                        .to_string(),
+                    format!("{:?}", t),
+                ))
             }
-            _ => return true,
         }
     }
 
-    true
+    for f in fields {
+        let schema = schemas
+            .iter_mut()
+            .find(|s| s.name == f.what.0)
+            .ok_or_else(|| Error::UnknownTable(f.what.0.clone()))?;
+
+        schema.fields.push(f);
+    }
+
+    for i in indexes {
+        let schema = schemas
+            .iter_mut()
+            .find(|s| s.name == i.what.0)
+            .ok_or_else(|| Error::UnknownTable(i.what.0.clone()))?;
+
+        schema.indexes.push(i);
+    }
+
+    Ok(schemas)
+}
+
+/// The statements needed to define `schemas`, one table after another.
+pub fn definitions(schemas: &[TableSchema]) -> String {
+    schemas
+        .iter()
+        .map(|ts| ts.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 pub async fn get_schemas_from_migrations(
@@ -202,28 +470,41 @@ pub async fn get_schemas_from_migrations(
 #[cfg(test)]
 mod tests {
     use insta::assert_snapshot;
-    use surrealdb::sql::parse;
+    use surrealdb::sql::{parse, Kind};
 
     use super::{
-        extract_define_field_from_define, extract_define_statement, get_schemas_from_migrations,
-        is_nullable,
+        allowed_values, definitions, extract_define_field_from_define, extract_define_statement,
+        field_value, get_schemas_from_migrations, is_nullable, linked_table,
+        schemas_from_definitions, FieldValue,
+    };
+    use crate::{
+        query::{Column, ColumnType},
+        read_migrations, Error,
     };
-    use crate::{read_migrations, Error};
 
     #[tokio::test]
     async fn test_get_schemas_from_migrations() {
         let migs = read_migrations("../blackbird/examples/migrations").unwrap();
         let schemas = get_schemas_from_migrations(migs).await.unwrap();
 
-        let schema_str = schemas
-            .into_iter()
-            .map(|ts| ts.to_string())
-            .collect::<Vec<_>>()
-            .join("\n");
+        let schema_str = definitions(&schemas);
 
         assert_snapshot!(schema_str)
     }
 
+    #[tokio::test]
+    async fn test_schemas_from_definitions_round_trips() -> Result<(), Error> {
+        let migs = read_migrations("../blackbird/examples/migrations")?;
+        let schemas = get_schemas_from_migrations(migs).await?;
+        let schema_str = definitions(&schemas);
+
+        let reparsed = schemas_from_definitions(parse(&schema_str)?.0 .0)?;
+
+        assert_eq!(schema_str, definitions(&reparsed));
+
+        Ok(())
+    }
+
     #[test]
     fn test_is_nullable_non_nullable_field() -> Result<(), Error> {
         let statement = r#"DEFINE FIELD name ON person TYPE string ASSERT $value != NONE;"#;
@@ -247,4 +528,139 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_is_nullable_with_other_conditions() -> Result<(), Error> {
+        let statement = r#"DEFINE FIELD name ON person TYPE string ASSERT (string::len($value) > 2) AND ($value != NONE)"#;
+        let parsed_statement = parse(&statement)?.0 .0[0].clone();
+        let fdef = extract_define_statement(parsed_statement)
+            .and_then(extract_define_field_from_define)?;
+
+        assert!(!is_nullable(&fdef));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_allowed_values() -> Result<(), Error> {
+        let statement = r#"DEFINE FIELD status ON post TYPE string ASSERT $value INSIDE ['draft', 'published']"#;
+        let parsed_statement = parse(&statement)?.0 .0[0].clone();
+        let fdef = extract_define_statement(parsed_statement)
+            .and_then(extract_define_field_from_define)?;
+
+        assert_eq!(
+            allowed_values(&fdef),
+            Some(vec!["draft".to_string(), "published".to_string()])
+        );
+        assert!(!is_nullable(&fdef));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_value() -> Result<(), Error> {
+        let fdefs = parse(
+            r#"DEFINE FIELD name ON person TYPE string;
+            DEFINE FIELD role ON person TYPE string VALUE $value OR 'member';
+            DEFINE FIELD created ON person TYPE datetime VALUE time::now();
+            DEFINE FIELD email ON person TYPE string VALUE string::lowercase($value);
+            DEFINE FIELD kind ON person TYPE string VALUE '$value';
+            DEFINE FIELD score ON person TYPE int VALUE math::max([$value, 0]);"#,
+        )?
+        .0
+         .0
+        .into_iter()
+        .map(|s| extract_define_statement(s).and_then(extract_define_field_from_define))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(field_value(&fdefs[0]), FieldValue::Plain);
+        assert!(
+            matches!(field_value(&fdefs[1]), FieldValue::Defaulted(v) if v.to_string() == "'member'")
+        );
+        assert!(matches!(field_value(&fdefs[2]), FieldValue::Computed(_)));
+        assert_eq!(field_value(&fdefs[3]), FieldValue::Plain);
+        assert!(matches!(field_value(&fdefs[4]), FieldValue::Computed(_)));
+        assert_eq!(field_value(&fdefs[5]), FieldValue::Plain);
+
+        assert!(!is_nullable(&fdefs[1]));
+        assert!(!is_nullable(&fdefs[2]));
+        assert!(is_nullable(&fdefs[3]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_edge() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE TABLE likes SCHEMAFULL;
+                DEFINE FIELD in ON likes TYPE record(person);
+                DEFINE FIELD out ON likes TYPE record(post);",
+            )?
+            .0
+             .0,
+        )?;
+
+        assert!(!schemas[0].is_edge());
+        assert!(schemas[1].is_edge());
+
+        let kind = schemas[1].field("out").and_then(|f| f.kind.as_ref());
+        assert_eq!(kind.and_then(linked_table), Some("post"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_schemas_with_indexes() -> Result<(), Error> {
+        let migrations = parse(
+            "DEFINE TABLE person SCHEMAFULL;
+            DEFINE FIELD username ON person TYPE string;
+            DEFINE FIELD name ON person TYPE string;
+            DEFINE INDEX person_username ON person FIELDS username UNIQUE;
+            DEFINE INDEX person_name ON person FIELDS name;",
+        )?;
+        let schemas = get_schemas_from_migrations(migrations.0 .0).await?;
+
+        assert_eq!(schemas[0].indexes.len(), 2);
+        assert!(schemas[0].has_unique_index("username"));
+        assert!(!schemas[0].has_unique_index("name"));
+
+        let reparsed = schemas_from_definitions(parse(&definitions(&schemas))?.0 .0)?;
+        assert_eq!(definitions(&schemas), definitions(&reparsed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_view_columns() -> Result<(), Error> {
+        let schemas = schemas_from_definitions(
+            parse(
+                "DEFINE TABLE person SCHEMAFULL;
+                DEFINE FIELD name ON person TYPE string;
+                DEFINE TABLE named AS SELECT name FROM person WHERE name != NONE;",
+            )?
+            .0
+             .0,
+        )?;
+
+        assert!(!schemas[0].is_view());
+        assert!(schemas[1].is_view());
+        assert_eq!(schemas[0].view_columns(&schemas)?, None);
+
+        let columns = schemas[1].view_columns(&schemas)?.unwrap();
+        assert_eq!(
+            columns,
+            vec![Column {
+                name: "name".to_string(),
+                path: vec!["name".to_string()],
+                ty: ColumnType::Value {
+                    kind: Some(Kind::String),
+                    nullable: true,
+                },
+            }]
+        );
+
+        Ok(())
+    }
 }
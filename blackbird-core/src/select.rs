@@ -0,0 +1,374 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use surrealdb::{
+    sql::{Statement, Table, Value},
+    Datastore, Session,
+};
+
+use super::{
+    convert::{take_path, FromValue, ToValue},
+    parse_single_statement, run_single_statement, Error,
+};
+
+/// A field of a table holding values of type `T`, generated as constants like
+/// `person::username` for building queries with [`SelectBuilder`].
+pub struct FieldRef<T> {
+    path: &'static [&'static str],
+    ty: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for FieldRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FieldRef<T> {}
+
+impl<T> FieldRef<T> {
+    pub const fn new(path: &'static [&'static str]) -> Self {
+        Self {
+            path,
+            ty: PhantomData,
+        }
+    }
+
+    pub fn path(&self) -> &'static [&'static str] {
+        self.path
+    }
+
+    fn compare(&self, op: &str, value: String) -> Condition {
+        Condition(format!("{} {op} {value}", idiom_sql(self.path)))
+    }
+
+    pub fn is_none(&self) -> Condition {
+        self.compare("=", "NONE".to_string())
+    }
+
+    pub fn is_some(&self) -> Condition {
+        self.compare("!=", "NONE".to_string())
+    }
+
+    pub fn asc(&self) -> Ordering {
+        Ordering(format!("{} ASC", idiom_sql(self.path)))
+    }
+
+    pub fn desc(&self) -> Ordering {
+        Ordering(format!("{} DESC", idiom_sql(self.path)))
+    }
+}
+
+impl<T: ToValue> FieldRef<T> {
+    fn compare_value<V: Into<T>>(&self, op: &str, value: V) -> Condition {
+        self.compare(op, value.into().to_value().to_string())
+    }
+
+    pub fn eq<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value("=", value)
+    }
+
+    pub fn ne<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value("!=", value)
+    }
+
+    pub fn lt<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value("<", value)
+    }
+
+    pub fn lte<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value("<=", value)
+    }
+
+    pub fn gt<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value(">", value)
+    }
+
+    pub fn gte<V: Into<T>>(&self, value: V) -> Condition {
+        self.compare_value(">=", value)
+    }
+
+    pub fn inside<I, V>(&self, values: I) -> Condition
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<T>,
+    {
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|v| v.into().to_value().to_string())
+            .collect();
+
+        self.compare("INSIDE", format!("[{}]", values.join(", ")))
+    }
+}
+
+/// Writes a field's path as SurrealQL, escaping any parts that aren't plain identifiers.
+fn idiom_sql(path: &[&str]) -> String {
+    path.iter()
+        .map(|part| {
+            let plain = part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !part.starts_with(|c: char| c.is_ascii_digit());
+
+            if plain && !part.is_empty() {
+                part.to_string()
+            } else {
+                format!("`{}`", part.replace('`', "\\`"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// A `WHERE` condition. Combined conditions are parenthesized, since SurrealQL doesn't have
+/// operator precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition(String);
+
+impl Condition {
+    pub fn and(self, other: Condition) -> Condition {
+        Condition(format!("({}) AND ({})", self.0, other.0))
+    }
+
+    pub fn or(self, other: Condition) -> Condition {
+        Condition(format!("({}) OR ({})", self.0, other.0))
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A field to `ORDER BY`, ascending unless made with [`FieldRef::desc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ordering(String);
+
+impl<T> From<FieldRef<T>> for Ordering {
+    fn from(field: FieldRef<T>) -> Self {
+        field.asc()
+    }
+}
+
+/// Builds a `SELECT * FROM` a table, with the rows decoded into `T`.
+///
+/// ```ignore
+/// let statement = Person::select()
+///     .filter(person::username.eq("b0b"))
+///     .order_by(person::name)
+///     .limit(10)
+///     .statement()?;
+/// ```
+pub struct SelectBuilder<T> {
+    table: &'static str,
+    cond: Option<Condition>,
+    order: Vec<Ordering>,
+    limit: Option<u64>,
+    start: Option<u64>,
+    row: PhantomData<fn() -> T>,
+}
+
+impl<T> SelectBuilder<T> {
+    pub fn new(table: &'static str) -> Self {
+        Self {
+            table,
+            cond: None,
+            order: Vec::new(),
+            limit: None,
+            start: None,
+            row: PhantomData,
+        }
+    }
+
+    /// Adds a condition, which has to hold along with any added before.
+    pub fn filter(mut self, cond: Condition) -> Self {
+        self.cond = Some(match self.cond {
+            Some(existing) => existing.and(cond),
+            None => cond,
+        });
+        self
+    }
+
+    pub fn order_by<O: Into<Ordering>>(mut self, order: O) -> Self {
+        self.order.push(order.into());
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn start(mut self, start: u64) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    pub fn sql(&self) -> String {
+        let mut sql = format!("SELECT * FROM {}", Table::from(self.table));
+
+        if let Some(cond) = &self.cond {
+            sql.push_str(&format!(" WHERE {cond}"));
+        }
+        if !self.order.is_empty() {
+            let order: Vec<_> = self.order.iter().map(|o| o.0.as_str()).collect();
+            sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+        if let Some(start) = self.start {
+            sql.push_str(&format!(" START {start}"));
+        }
+
+        sql
+    }
+
+    pub fn statement(&self) -> Result<Statement, Error> {
+        parse_single_statement(&self.sql())
+    }
+}
+
+impl<T: FromValue> SelectBuilder<T> {
+    pub async fn fetch(&self, ds: &Datastore, sess: &Session) -> Result<Vec<T>, Error> {
+        let result = run_single_statement(ds, sess, self.statement()?, None).await?;
+
+        Vec::<T>::from_value(result)
+    }
+
+    /// Fetches `size` rows from `start`, with `LIMIT` and `START`. The next page starts from
+    /// [`Page::next`], if there's anything left. `size` can't be 0.
+    pub async fn fetch_page(
+        self,
+        ds: &Datastore,
+        sess: &Session,
+        start: u64,
+        size: u64,
+    ) -> Result<Page<T, u64>, Error> {
+        if size == 0 {
+            return Err(Error::EmptyPage);
+        }
+
+        // Asking for one more than we need tells us whether there's another page
+        let mut items = self
+            .limit(size.saturating_add(1))
+            .start(start)
+            .fetch(ds, sess)
+            .await?;
+
+        let next = if items.len() as u64 > size {
+            items.truncate(size as usize);
+            Some(start.saturating_add(size))
+        } else {
+            None
+        };
+
+        Ok(Page { items, next })
+    }
+
+    /// Fetches `size` rows ordered by `field`, starting after the cursor `after`, which is the
+    /// [`Page::next`] of the previous page. Any other ordering is replaced.
+    ///
+    /// Unlike [`SelectBuilder::fetch_page`], rows added or removed between requests don't shift
+    /// the pages, but `field` has to be unique, or rows sharing a value across the end of a page
+    /// are skipped. The generated `list_by_` functions are only made for fields with a `UNIQUE`
+    /// index. `size` can't be 0.
+    pub async fn fetch_page_after<C>(
+        mut self,
+        ds: &Datastore,
+        sess: &Session,
+        field: FieldRef<C>,
+        after: Option<C>,
+        size: u64,
+    ) -> Result<Page<T, C>, Error>
+    where
+        C: ToValue + FromValue,
+    {
+        if size == 0 {
+            return Err(Error::EmptyPage);
+        }
+
+        self.order = vec![field.asc()];
+        if let Some(after) = after {
+            self = self.filter(field.gt(after));
+        }
+        let select = self.limit(size.saturating_add(1));
+
+        let mut rows = match run_single_statement(ds, sess, select.statement()?, None).await? {
+            Value::Array(rows) => rows.0,
+            v => {
+                return Err(Error::UnexpectedType(
+                    "Array".to_string(),
+                    format!("{:?}", v),
+                ))
+            }
+        };
+
+        let next = if rows.len() as u64 > size {
+            rows.truncate(size as usize);
+            match rows.last() {
+                Some(Value::Object(last)) => {
+                    Some(C::from_value(take_path(&mut last.clone(), field.path()))?)
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let items = rows
+            .into_iter()
+            .map(T::from_value)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Page { items, next })
+    }
+}
+
+/// One page of rows, along with where the next page starts, if there is one. `C` is a `u64`
+/// offset for [`SelectBuilder::fetch_page`], or the value of the field being paged by for
+/// [`SelectBuilder::fetch_page_after`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T, C> {
+    pub items: Vec<T>,
+    pub next: Option<C>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldRef, SelectBuilder};
+    use crate::Error;
+
+    const USERNAME: FieldRef<String> = FieldRef::new(&["username"]);
+    const AGE: FieldRef<i64> = FieldRef::new(&["age"]);
+    const FIRST_LINE: FieldRef<String> = FieldRef::new(&["address", "first-line"]);
+
+    #[test]
+    fn test_select_builder() -> Result<(), Error> {
+        let select = SelectBuilder::<()>::new("person")
+            .filter(USERNAME.eq("b0b"))
+            .filter(AGE.gt(17).or(AGE.is_none()))
+            .order_by(USERNAME)
+            .order_by(AGE.desc())
+            .limit(10)
+            .start(20);
+
+        assert_eq!(
+            select.sql(),
+            "SELECT * FROM person WHERE (username = 'b0b') AND ((age > 17) OR (age = NONE)) \
+             ORDER BY username ASC, age DESC LIMIT 10 START 20"
+        );
+        select.statement()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escapes_field_names() {
+        let cond = FIRST_LINE.inside(["Wellington", "Auckland"]);
+
+        assert_eq!(
+            cond.to_string(),
+            "address.`first-line` INSIDE ['Wellington', 'Auckland']"
+        );
+    }
+}
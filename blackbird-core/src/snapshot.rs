@@ -0,0 +1,159 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use surrealdb::sql::parse;
+
+use super::{
+    hash_migration_files, parse_migration_files, read_migration_files,
+    schema::{definitions, get_schemas_from_migrations, schemas_from_definitions, TableSchema},
+    Context, Error,
+};
+
+pub const SCHEMA_FILE: &str = "schema.surql";
+pub const METADATA_FILE: &str = "schema.json";
+
+/// Information about the migrations a snapshot was taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    /// Hex encoded [`hash_migration_files`] of the migrations
+    pub migrations_hash: String,
+    pub migrations: Vec<String>,
+    pub tables: Vec<String>,
+}
+
+/// The schema that results from applying a set of migrations, so builds can use it without
+/// having to execute them.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub schemas: Vec<TableSchema>,
+    pub metadata: SnapshotMetadata,
+}
+
+impl Snapshot {
+    pub async fn from_migrations<P>(directory: P) -> Result<Snapshot, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let files = read_migration_files(directory)?;
+        let schemas = get_schemas_from_migrations(parse_migration_files(&files)?).await?;
+
+        let metadata = SnapshotMetadata {
+            migrations_hash: format!("{:016x}", hash_migration_files(&files)),
+            migrations: files
+                .iter()
+                .map(|f| {
+                    f.path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect(),
+            tables: schemas.iter().map(|s| s.name.clone()).collect(),
+        };
+
+        Ok(Snapshot { schemas, metadata })
+    }
+
+    /// Reads a snapshot previously written to `directory` by [`Snapshot::write`].
+    pub fn read<P>(directory: P) -> Result<Snapshot, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let (schema_path, metadata_path) = snapshot_files(directory.as_ref());
+
+        let schema_str = fs::read_to_string(&schema_path)
+            .with_context(|| format!("could not read {}", schema_path.to_string_lossy()))?;
+        let metadata_str = fs::read_to_string(&metadata_path)
+            .with_context(|| format!("could not read {}", metadata_path.to_string_lossy()))?;
+
+        Ok(Snapshot {
+            schemas: schemas_from_definitions(parse(&schema_str)?.0 .0)?,
+            metadata: serde_json::from_str(&metadata_str)?,
+        })
+    }
+
+    /// Writes `schema.surql` and `schema.json` into `directory`.
+    pub fn write<P>(&self, directory: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)
+            .with_context(|| format!("could not create {}", directory.to_string_lossy()))?;
+
+        let (schema_path, metadata_path) = snapshot_files(directory);
+        let (schema_str, metadata_str) = self.render()?;
+
+        fs::write(&schema_path, schema_str)
+            .with_context(|| format!("could not write {}", schema_path.to_string_lossy()))?;
+        fs::write(&metadata_path, metadata_str)
+            .with_context(|| format!("could not write {}", metadata_path.to_string_lossy()))
+    }
+
+    /// Checks the snapshot in `directory` is exactly what [`Snapshot::write`] would write for
+    /// this one.
+    pub fn check<P>(&self, directory: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let (schema_path, metadata_path) = snapshot_files(directory.as_ref());
+        let (schema_str, metadata_str) = self.render()?;
+
+        for (path, expected) in [(schema_path, schema_str), (metadata_path, metadata_str)] {
+            if fs::read_to_string(&path).ok().as_ref() != Some(&expected) {
+                return Err(Error::StaleSnapshot(path.to_string_lossy().to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render(&self) -> Result<(String, String), Error> {
+        let mut metadata_str = serde_json::to_string_pretty(&self.metadata)?;
+        metadata_str.push('\n');
+
+        Ok((definitions(&self.schemas), metadata_str))
+    }
+}
+
+fn snapshot_files(directory: &Path) -> (PathBuf, PathBuf) {
+    (directory.join(SCHEMA_FILE), directory.join(METADATA_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::Snapshot;
+    use crate::{schema::definitions, Error};
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip_and_check() -> Result<(), Error> {
+        let dir = env::temp_dir().join(format!("blackbird-snapshot-test-{}", process::id()));
+        let snapshot = Snapshot::from_migrations("../blackbird/examples/migrations").await?;
+
+        assert!(matches!(snapshot.check(&dir), Err(Error::StaleSnapshot(_))));
+
+        snapshot.write(&dir)?;
+        snapshot.check(&dir)?;
+
+        let read = Snapshot::read(&dir)?;
+        assert_eq!(read.metadata, snapshot.metadata);
+        assert_eq!(definitions(&read.schemas), definitions(&snapshot.schemas));
+
+        fs::write(
+            dir.join(super::SCHEMA_FILE),
+            "DEFINE TABLE person SCHEMALESS;",
+        )
+        .unwrap();
+        assert!(matches!(snapshot.check(&dir), Err(Error::StaleSnapshot(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::{fmt::Display, sync::OnceLock};
+
+use regex::Regex;
+use surrealdb::sql::{statements::DefineFieldStatement, Function, Number, Operator, Value};
+
+use super::schema::{assert_conditions, is_value_param, parenthesized};
+
+/// A literal a field is compared against in an `ASSERT`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    None,
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Literal {
+    fn from_value(value: &Value) -> Option<Literal> {
+        match value {
+            Value::None => Some(Literal::None),
+            Value::Null => Some(Literal::Null),
+            Value::Number(Number::Int(i)) => Some(Literal::Int(*i)),
+            Value::Number(Number::Float(f)) => Some(Literal::Float(*f)),
+            Value::Strand(s) => Some(Literal::String(s.0.clone())),
+            v if v.is_true() => Some(Literal::Bool(true)),
+            v if v.is_false() => Some(Literal::Bool(false)),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Literal::None => Value::None,
+            Literal::Null => Value::Null,
+            Literal::Bool(b) => Value::from(*b),
+            Literal::Int(i) => Value::from(*i),
+            Literal::Float(f) => Value::from(*f),
+            Literal::String(s) => Value::from(s.as_str()),
+        }
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_value().fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    MoreThan,
+    MoreThanOrEqual,
+}
+
+impl Comparison {
+    fn from_operator(op: &Operator) -> Option<Comparison> {
+        match op {
+            Operator::Equal => Some(Comparison::Equal),
+            Operator::NotEqual => Some(Comparison::NotEqual),
+            Operator::LessThan => Some(Comparison::LessThan),
+            Operator::LessThanOrEqual => Some(Comparison::LessThanOrEqual),
+            Operator::MoreThan => Some(Comparison::MoreThan),
+            Operator::MoreThanOrEqual => Some(Comparison::MoreThanOrEqual),
+            _ => None,
+        }
+    }
+
+    /// The comparison with its sides swapped, so `5 < $value` can become `$value > 5`.
+    fn flip(self) -> Comparison {
+        match self {
+            Comparison::LessThan => Comparison::MoreThan,
+            Comparison::LessThanOrEqual => Comparison::MoreThanOrEqual,
+            Comparison::MoreThan => Comparison::LessThan,
+            Comparison::MoreThanOrEqual => Comparison::LessThanOrEqual,
+            c => c,
+        }
+    }
+
+    fn holds<T: PartialOrd>(self, l: &T, r: &T) -> bool {
+        match self {
+            Comparison::Equal => l == r,
+            Comparison::NotEqual => l != r,
+            Comparison::LessThan => l < r,
+            Comparison::LessThanOrEqual => l <= r,
+            Comparison::MoreThan => l > r,
+            Comparison::MoreThanOrEqual => l >= r,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::LessThan => "<",
+            Comparison::LessThanOrEqual => "<=",
+            Comparison::MoreThan => ">",
+            Comparison::MoreThanOrEqual => ">=",
+        })
+    }
+}
+
+/// One of the conditions from a field's `ASSERT` that we know how to check without the database.
+/// A regex from an `ASSERT`, compiled the first time it's matched against.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    compiled: OnceLock<Option<Regex>>,
+}
+
+impl Pattern {
+    pub fn new(source: impl Into<String>) -> Pattern {
+        Pattern {
+            source: source.into(),
+            compiled: OnceLock::new(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether `s` matches. A pattern the regex crate can't compile never does.
+    pub fn is_match(&self, s: &str) -> bool {
+        self.compiled
+            .get_or_init(|| Regex::new(&self.source).ok())
+            .as_ref()
+            .is_some_and(|re| re.is_match(s))
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.source == other.source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    /// `$value > 0`
+    Compare(Comparison, Literal),
+    /// `string::len($value) <= 20`
+    Length(Comparison, i64),
+    /// `$value = /^[a-z]+$/`
+    Matches(Pattern),
+    /// `$value INSIDE ['draft', 'published']`
+    Inside(Vec<Literal>),
+}
+
+impl Constraint {
+    pub fn holds(&self, value: &Value) -> bool {
+        match self {
+            Constraint::Compare(c, literal) => c.holds(value, &literal.to_value()),
+            Constraint::Length(c, len) => {
+                let actual = match value {
+                    Value::Strand(s) => s.0.chars().count(),
+                    Value::None | Value::Null => 0,
+                    v => v.to_string().chars().count(),
+                };
+
+                c.holds(&(actual as i64), len)
+            }
+            Constraint::Matches(pattern) => match value {
+                Value::Strand(s) => pattern.is_match(&s.0),
+                _ => false,
+            },
+            Constraint::Inside(literals) => literals.iter().any(|l| *value == l.to_value()),
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constraint::Compare(c, literal) => write!(f, "$value {c} {literal}"),
+            Constraint::Length(c, len) => write!(f, "string::len($value) {c} {len}"),
+            Constraint::Matches(pattern) => write!(f, "$value = /{}/", pattern.as_str()),
+            Constraint::Inside(literals) => {
+                let literals: Vec<_> = literals.iter().map(Literal::to_string).collect();
+                write!(f, "$value INSIDE [{}]", literals.join(", "))
+            }
+        }
+    }
+}
+
+fn is_len_of_value(function: &Function) -> bool {
+    matches!(function, Function::Normal(name, args)
+        if name == "string::len" && matches!(args.as_slice(), [arg] if is_value_param(arg)))
+}
+
+fn is_len_call(value: &Value) -> bool {
+    matches!(value, Value::Function(f) if is_len_of_value(f))
+}
+
+fn constraint_for(l: &Value, c: Comparison, r: &Value) -> Option<Constraint> {
+    if is_value_param(l) {
+        if let Value::Regex(re) = r {
+            let pattern = re.to_string();
+            let pattern = pattern.strip_prefix('/')?.strip_suffix('/')?;
+            return (c == Comparison::Equal).then(|| Constraint::Matches(Pattern::new(pattern)));
+        }
+
+        return Literal::from_value(r).map(|literal| Constraint::Compare(c, literal));
+    }
+
+    if is_len_call(l) {
+        return match r {
+            Value::Number(Number::Int(len)) => Some(Constraint::Length(c, *len)),
+            _ => None,
+        };
+    }
+
+    None
+}
+
+fn condition_constraint(condition: &Value) -> Option<Constraint> {
+    let e = match condition {
+        Value::Expression(e) => e,
+        Value::Subquery(s) => return parenthesized(s).and_then(condition_constraint),
+        _ => return None,
+    };
+
+    if e.o == Operator::Inside && is_value_param(&e.l) {
+        return match &e.r {
+            Value::Array(values) => values
+                .0
+                .iter()
+                .map(Literal::from_value)
+                .collect::<Option<Vec<_>>>()
+                .map(Constraint::Inside),
+            _ => None,
+        };
+    }
+
+    let c = Comparison::from_operator(&e.o)?;
+
+    constraint_for(&e.l, c, &e.r).or_else(|| constraint_for(&e.r, c.flip(), &e.l))
+}
+
+/// The parts of a field's `ASSERT` that can be checked client side. Anything we don't understand
+/// (`OR`s, other functions, comparisons with other fields) is left to the database.
+pub fn constraints(field: &DefineFieldStatement) -> Vec<Constraint> {
+    assert_conditions(field)
+        .into_iter()
+        .filter_map(condition_constraint)
+        .collect()
+}
+
+/// A field that didn't meet one of its constraints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub field: String,
+    pub constraint: Constraint,
+}
+
+/// Every constraint that didn't hold, as returned from the generated `validate` methods.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    /// Records an error for every one of `constraints` that doesn't hold for `value`.
+    pub fn check(&mut self, field: &str, value: &Value, constraints: &[Constraint]) {
+        for c in constraints {
+            if !c.holds(value) {
+                self.errors.push(ValidationError {
+                    field: field.to_string(),
+                    constraint: c.clone(),
+                });
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|e| format!("{} must satisfy {}", e.field, e.constraint))
+            .collect();
+
+        f.write_str(&errors.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::sql::{parse, statements::DefineStatement, Statement, Value};
+
+    use super::{constraints, Comparison, Constraint, Literal, Pattern, ValidationErrors};
+    use crate::Error;
+
+    fn field_constraints(definition: &str) -> Result<Vec<Constraint>, Error> {
+        match parse(definition)?.0 .0.remove(0) {
+            Statement::Define(DefineStatement::Field(f)) => Ok(constraints(&f)),
+            s => Err(Error::UnexpectedType(
+                "DefineStatement::Field".to_string(),
+                format!("{:?}", s),
+            )),
+        }
+    }
+
+    #[test]
+    fn test_constraints_from_assert() -> Result<(), Error> {
+        let cs = field_constraints(
+            "DEFINE FIELD username ON person TYPE string
+             ASSERT ($value != NONE) AND (string::len($value) <= 20) AND (3 < string::len($value))",
+        )?;
+
+        assert_eq!(
+            cs,
+            vec![
+                Constraint::Compare(Comparison::NotEqual, Literal::None),
+                Constraint::Length(Comparison::LessThanOrEqual, 20),
+                Constraint::Length(Comparison::MoreThan, 3),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_conditions_are_skipped() -> Result<(), Error> {
+        let cs = field_constraints(
+            "DEFINE FIELD age ON person TYPE int ASSERT ($value = NONE OR $value > 0) AND ($value <= 150)",
+        )?;
+
+        assert_eq!(
+            cs,
+            vec![Constraint::Compare(
+                Comparison::LessThanOrEqual,
+                Literal::Int(150)
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_collects_every_error() {
+        let mut errors = ValidationErrors::default();
+        let constraints = [
+            Constraint::Length(Comparison::MoreThan, 3),
+            Constraint::Matches(Pattern::new("^[a-z]+$")),
+            Constraint::Inside(vec![Literal::String("Bob".to_string())]),
+        ];
+
+        errors.check("username", &Value::from("Bob"), &constraints);
+        assert_eq!(errors.errors.len(), 2);
+        assert_eq!(
+            errors.to_string(),
+            "username must satisfy string::len($value) > 3, username must satisfy $value = /^[a-z]+$/"
+        );
+
+        let mut errors = ValidationErrors::default();
+        errors.check("username", &Value::from("bobby"), &constraints[..2]);
+        assert!(errors.into_result().is_ok());
+    }
+
+    #[test]
+    fn test_pattern_is_compiled_once() {
+        let pattern = Pattern::new("^[a-z]+$");
+        assert!(pattern.is_match("bob"));
+        assert!(!pattern.is_match("Bob"));
+        assert!(pattern.compiled.get().is_some());
+
+        assert!(!Pattern::new("(").is_match("("));
+    }
+}
@@ -1,78 +1,288 @@
-use core::panic;
+#![cfg_attr(feature = "nightly", feature(track_path))]
+
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
     sync::RwLock,
 };
-use surrealdb::sql::Kind;
+use surrealdb::sql::{parse, Statement};
 
 use blackbird_core::{
-    read_migrations,
-    schema::{get_schemas_from_migrations, is_nullable, TableSchema},
-    Error,
+    cache::{read_cached_schemas, write_cached_schemas},
+    codegen::{field_ident, row_items, schema_items, struct_items, StructOptions},
+    hash_migration_files, parse_migration_files,
+    query::{infer_select_columns, validate_query, Column},
+    read_migration_files,
+    schema::{field_name, get_schemas_from_migrations, TableSchema},
+    snapshot::{Snapshot, SCHEMA_FILE},
+    Error, MigrationFile,
+};
+use migration_path::{
+    metadata_type_overrides, migration_dir, offline_snapshot_dir, schema_cache_dir, MigrationPath,
 };
-use once_cell::unsync::Lazy;
-use syn::{parse::Parse, parse_macro_input, Ident, LitStr, Token, Type};
+use once_cell::sync::Lazy;
+use syn::{
+    braced,
+    ext::IdentExt,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    token::Brace,
+    Attribute, Ident, LitStr, Token, Type, Visibility,
+};
+
+mod migration_path;
 
-const DEFAULT_MIGRATION_PATH: &str = "./src/migrations";
-const SCHEMAS_BY_MIGRATION_PATH: Lazy<RwLock<HashMap<PathBuf, Vec<TableSchema>>>> =
+/// Schemas keyed by migration directory, along with a hash of the migrations they were built from
+static SCHEMAS_BY_MIGRATION_PATH: Lazy<RwLock<HashMap<PathBuf, (u64, Vec<TableSchema>)>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
-fn get_schema_from_local_cache<P>(migrations_directory: P) -> Result<Vec<TableSchema>, Error>
+fn get_schema_from_local_cache<P>(
+    migrations_directory: P,
+) -> Result<(Vec<MigrationFile>, Vec<TableSchema>), Error>
 where
     P: AsRef<Path>,
 {
     let path = migrations_directory.as_ref();
-    if let Some(ts) = SCHEMAS_BY_MIGRATION_PATH
-        .read()
-        .ok()
-        .and_then(|sbmp| sbmp.get(path).cloned())
-    {
-        Ok(ts)
+    let files = read_migration_files(path)?;
+    let hash = hash_migration_files(&files);
+
+    if let Some(ts) = SCHEMAS_BY_MIGRATION_PATH.read().ok().and_then(|sbmp| {
+        sbmp.get(path)
+            .filter(|(cached_hash, _)| *cached_hash == hash)
+            .map(|(_, ts)| ts.clone())
+    }) {
+        Ok((files, ts))
     } else {
-        let migs = read_migrations(&migrations_directory)?;
+        let cache_dir = schema_cache_dir();
+        let cached = cache_dir
+            .as_ref()
+            .and_then(|dir| read_cached_schemas(dir, hash));
+
+        let schemas = match cached {
+            Some(schemas) => schemas,
+            None => {
+                let migs = parse_migration_files(&files)?;
+
+                // starting a runtime & block_on is horrible, can we just compress the schema affecting queries instead?
+                let schemas = tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(get_schemas_from_migrations(migs))?;
 
-        // starting a runtime & block_on is horrible, can we just compress the schema affecting queries instead?
-        let schemas = tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(get_schemas_from_migrations(migs))?;
+                if let Some(dir) = cache_dir.as_ref() {
+                    // Not being able to cache shouldn't stop the build, it'll just be slower
+                    write_cached_schemas(dir, hash, &schemas).ok();
+                }
+
+                schemas
+            }
+        };
 
         SCHEMAS_BY_MIGRATION_PATH
             .write()
             .ok()
-            .and_then(|mut sbmp| sbmp.insert(path.to_path_buf(), schemas.clone()));
+            .and_then(|mut sbmp| sbmp.insert(path.to_path_buf(), (hash, schemas.clone())));
+
+        Ok((files, schemas))
+    }
+}
+
+/// The schemas built from a migration directory, and the files they came from.
+struct Migrations {
+    path: String,
+    files: Vec<PathBuf>,
+    schemas: Vec<TableSchema>,
+}
+
+impl Migrations {
+    /// Cargo doesn't know our output depends on the migration files, so we `include_bytes!` them
+    /// to get them into the dependency info, and a rebuild when one changes.
+    ///
+    /// New files in the migration directory only get picked up on nightly, with the `nightly`
+    /// feature enabled.
+    fn track(&self) -> proc_macro2::TokenStream {
+        let files = self.files.iter().map(|f| f.display().to_string());
+
+        quote! {
+            const _: &[&[u8]] = &[#(include_bytes!(#files) as &[u8]),*];
+        }
+    }
+}
+
+fn load_schemas(path: Option<MigrationPath>, span: Span) -> syn::Result<Migrations> {
+    let span = path.as_ref().map(|p| p.span).unwrap_or(span);
+    let migration_path = migration_dir(path.as_ref())?;
+
+    if let Some(snapshot_dir) = offline_snapshot_dir(&migration_path)? {
+        let snapshot = Snapshot::read(&snapshot_dir).map_err(|e| {
+            syn::Error::new(
+                span,
+                format!(
+                    "Could not read schema snapshot from {}, try running `blackbird schema dump`: {e:?}",
+                    snapshot_dir.display()
+                ),
+            )
+        })?;
+
+        return Ok(Migrations {
+            path: snapshot_dir.display().to_string(),
+            files: vec![snapshot_dir.join(SCHEMA_FILE)],
+            schemas: snapshot.schemas,
+        });
+    }
+
+    #[cfg(feature = "nightly")]
+    proc_macro::tracked_path::path(migration_path.display().to_string());
+
+    let (files, schemas) = get_schema_from_local_cache(&migration_path).map_err(|e| {
+        syn::Error::new(
+            span,
+            format!(
+                "Could not get migrations from {}: {e:?}",
+                migration_path.display()
+            ),
+        )
+    })?;
+
+    Ok(Migrations {
+        path: migration_path.display().to_string(),
+        files: files.into_iter().map(|f| f.path).collect(),
+        schemas,
+    })
+}
+
+/// Parses an optional `, <path>` at the end of a macro's arguments.
+fn parse_trailing_path(input: ParseStream) -> syn::Result<Option<MigrationPath>> {
+    if input.parse::<Option<Token![,]>>()?.is_none() || input.is_empty() {
+        return Ok(None);
+    }
+
+    input.parse().map(Some)
+}
+
+mod kw {
+    syn::custom_keyword!(fields);
+    syn::custom_keyword!(overrides);
+}
+
+/// `#[attr] name` in the `fields { ... }` block of `struct_for!`.
+struct FieldAttrs {
+    attrs: Vec<Attribute>,
+    name: Ident,
+}
+
+impl Parse for FieldAttrs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            name: input.parse()?,
+        })
+    }
+}
+
+/// `name: Type` in the `overrides { ... }` block of `struct_for!`.
+struct FieldOverride {
+    name: Ident,
+    typ: Type,
+}
+
+impl Parse for FieldOverride {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+
+        Ok(Self {
+            name,
+            typ: input.parse()?,
+        })
+    }
+}
+
+/// Parses the contents of a `name { ... }` block in a macro's arguments.
+fn parse_block<T: Parse>(input: ParseStream) -> syn::Result<Punctuated<T, Token![,]>> {
+    let content;
+    braced!(content in input);
+    Punctuated::parse_terminated(&content)
+}
+
+/// Finds the name in the database of the field `name` refers to, which can either be the name of
+/// the generated rust field, or the name in the database if that's a valid identifier.
+fn check_field_exists(schema: &TableSchema, name: &Ident) -> syn::Result<String> {
+    let field_name = name.unraw().to_string();
 
-        Ok(schemas)
+    schema
+        .fields
+        .iter()
+        .map(field_name)
+        .find(|stored| {
+            *stored == field_name
+                || field_ident(stored).map_or(false, |ident| ident.unraw() == field_name)
+        })
+        .ok_or_else(|| {
+            syn::Error::new(
+                name.span(),
+                format!("Field {field_name} is not defined on table {}", schema.name),
+            )
+        })
+}
+
+/// The type overrides for `table` from `[package.metadata.blackbird.overrides]`.
+fn metadata_struct_options(table: &str, span: Span) -> syn::Result<StructOptions> {
+    let mut overrides = metadata_type_overrides()?;
+    let mut options = StructOptions::default();
+
+    for (field, typ) in overrides.remove(table).unwrap_or_default() {
+        options = options
+            .override_type(&field, &typ)
+            .map_err(|e| syn::Error::new(span, format!("{e} (in Cargo.toml)")))?;
     }
+
+    Ok(options)
 }
 
 struct StructForTable {
+    vis: Visibility,
     name: Ident,
     schema: TableSchema,
+    /// Every table in the migrations, which views need to work out their fields
+    schemas: Vec<TableSchema>,
+    options: StructOptions,
+    tracking: proc_macro2::TokenStream,
 }
 
 impl Parse for StructForTable {
-    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis: Visibility = input.parse()?;
         let name: Ident = input.parse()?;
-        input.parse::<Token![,]>()?;
-        let rel_path: Option<LitStr> = input.parse()?;
 
-        let migration_path = rel_path
-            .map(|ls| ls.value())
-            .unwrap_or_else(|| DEFAULT_MIGRATION_PATH.to_string());
+        let mut path = None;
+        let mut fields = Vec::new();
+        let mut overrides = Vec::new();
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            if input.peek(kw::fields) && input.peek2(Brace) {
+                input.parse::<kw::fields>()?;
+                fields.extend(parse_block::<FieldAttrs>(input)?);
+            } else if input.peek(kw::overrides) && input.peek2(Brace) {
+                input.parse::<kw::overrides>()?;
+                overrides.extend(parse_block::<FieldOverride>(input)?);
+            } else if path.is_none() {
+                path = Some(input.parse()?);
+            } else {
+                return Err(input.error("Expected `fields { ... }` or `overrides { ... }`"));
+            }
+        }
 
-        let schemas = get_schema_from_local_cache(&migration_path).map_err(|e| {
-            syn::Error::new(
-                name.span(),
-                format!("Could not get migrations from {migration_path}: {e:?}"),
-            )
-        })?;
+        let migrations = load_schemas(path, name.span())?;
+        let tracking = migrations.track();
+        let migration_path = migrations.path;
 
+        let schemas = migrations.schemas;
         let mut schemas_with_name = schemas
-            .into_iter()
+            .iter()
             .filter(|s| s.name.eq_ignore_ascii_case(&name.to_string()));
 
         let schema = schemas_with_name.next().ok_or_else(|| {
@@ -90,75 +300,235 @@ impl Parse for StructForTable {
                 format!("More than one table with name {name} in migrations from {migration_path}"),
             ));
         }
+        let schema = schema.clone();
+
+        let mut options = metadata_struct_options(&schema.name, name.span())?;
+        options.attrs = attrs;
 
-        Ok(Self { name, schema })
+        for f in fields {
+            options
+                .field_attrs
+                .entry(check_field_exists(&schema, &f.name)?)
+                .or_default()
+                .extend(f.attrs);
+        }
+
+        // Overrides in the macro take precedence over those in Cargo.toml
+        for o in overrides {
+            options
+                .type_overrides
+                .insert(check_field_exists(&schema, &o.name)?, o.typ);
+        }
+
+        Ok(Self {
+            vis,
+            name,
+            schema,
+            schemas,
+            options,
+            tracking,
+        })
     }
 }
 
-fn struct_field_for(field_name: &str, kind: Kind, nullable: bool) -> TokenStream {
-    let typ: Type = match kind {
-        Kind::Bool => Type::Verbatim(quote!(bool)),
-        Kind::Datetime => todo!(),
-        Kind::Decimal => todo!(),
-        Kind::Duration => todo!(),
-        Kind::Float => Type::Verbatim(quote!(f64)),
-        Kind::Int => Type::Verbatim(quote!(i64)),
-        Kind::Number => todo!(),
-        Kind::String => Type::Verbatim(quote!(String)),
-
-        // TODO: unhandled
-        Kind::Object => todo!(),
-        Kind::Record(_) => todo!(),
-        Kind::Geometry(_) => todo!(),
-        Kind::Any => todo!(),
-        Kind::Array => todo!(),
-    };
+struct SurqlQuery {
+    query: LitStr,
+    columns: Option<Vec<Column>>,
+    tracking: proc_macro2::TokenStream,
+}
 
-    let typ = if nullable {
-        Type::Verbatim(quote!(Option<#typ>))
-    } else {
-        typ
-    };
-    let field_name = Ident::new(field_name, Span::call_site());
+impl Parse for SurqlQuery {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let query: LitStr = input.parse()?;
+        let path = parse_trailing_path(input)?;
+
+        let migrations = load_schemas(path, query.span())?;
+        let Migrations {
+            path: migration_path,
+            schemas,
+            ..
+        } = &migrations;
+
+        let parsed = parse(&query.value())
+            .map_err(|e| syn::Error::new(query.span(), format!("Could not parse query: {e}")))?;
+
+        validate_query(&parsed, schemas).map_err(|e| {
+            syn::Error::new(
+                query.span(),
+                format!("{e} (using migrations from {migration_path})"),
+            )
+        })?;
 
-    quote! {
-        pub #field_name: #typ,
+        let columns = match parsed.0 .0.as_slice() {
+            [Statement::Select(s)] => infer_select_columns(s, schemas)
+                .map_err(|e| syn::Error::new(query.span(), e.to_string()))?,
+            _ => None,
+        };
+
+        Ok(Self {
+            query,
+            columns,
+            tracking: migrations.track(),
+        })
     }
-    .into()
 }
 
+/// Generates a struct for a single table, along with an id type and conversions to and from
+/// `Value`s.
+///
+/// Attributes and visibility before the name are put on the struct, and attributes for
+/// individual fields can be given in a `fields` block. An `overrides` block swaps the type of a
+/// field for one implementing `FromValue` and `ToValue`, as can
+/// `[package.metadata.blackbird.overrides]` in Cargo.toml, e.g. `"person.email" = "crate::Email"`.
+///
+/// Views, defined `AS SELECT ...`, only get a read only struct, with fields worked out from the
+/// table they select from.
+///
+/// ```ignore
+/// struct_for!(
+///     #[derive(Debug, Clone, PartialEq)]
+///     pub Person,
+///     "./examples/migrations",
+///     fields {
+///         #[doc = "How the person is shown to others"]
+///         name,
+///     },
+///     overrides {
+///         username: crate::Username,
+///     }
+/// );
+/// ```
 #[proc_macro]
 pub fn struct_for(input: TokenStream) -> TokenStream {
-    let StructForTable { name, schema } = parse_macro_input!(input as StructForTable);
+    let StructForTable {
+        vis,
+        name,
+        schema,
+        schemas,
+        options,
+        tracking,
+    } = parse_macro_input!(input as StructForTable);
 
-    let mut fields = Vec::with_capacity(schema.fields.len());
+    let struct_def = match struct_items(&quote!(#vis), &name, &schema, &schemas, &options) {
+        Ok(tokens) => tokens,
+        Err(e) => return syn::Error::new(name.span(), e).to_compile_error().into(),
+    };
 
-    for s in schema.fields {
-        let nullable = is_nullable(&s);
-        if let Some(kind) = s.kind {
-            fields.push(struct_field_for(
-                s.name.to_string().as_str(),
-                kind,
-                nullable,
-            ));
+    TokenStream::from(quote! {
+        #tracking
+        #struct_def
+    })
+}
+
+struct SchemaModule {
+    tracking: proc_macro2::TokenStream,
+    items: proc_macro2::TokenStream,
+}
+
+impl Parse for SchemaModule {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path: Option<MigrationPath> = if input.is_empty() {
+            None
         } else {
-            // TODO: compile error instead
-            panic!(
-                "Field {} on table {} must have a defined type.",
-                s.name, schema.name
-            )
+            Some(input.parse()?)
+        };
+        let span = path
+            .as_ref()
+            .map(|p| p.span)
+            .unwrap_or_else(Span::call_site);
+
+        let migrations = load_schemas(path, span)?;
+
+        // Overrides for a table that's been removed or misspelt would otherwise do nothing
+        for table in metadata_type_overrides()?.keys() {
+            if !migrations.schemas.iter().any(|s| &s.name == table) {
+                return Err(syn::Error::new(
+                    span,
+                    format!("{} (in Cargo.toml)", Error::UnknownTable(table.clone())),
+                ));
+            }
         }
+
+        let mut options = BTreeMap::new();
+        for s in migrations.schemas.iter() {
+            options.insert(s.name.clone(), metadata_struct_options(&s.name, span)?);
+        }
+
+        let items = schema_items(&migrations.schemas, &options).map_err(|e| {
+            syn::Error::new(
+                span,
+                format!("{e} (using migrations from {})", migrations.path),
+            )
+        })?;
+
+        Ok(Self {
+            tracking: migrations.track(),
+            items,
+        })
     }
+}
 
-    let fields = fields
-        .into_iter()
-        .map(proc_macro2::TokenStream::from)
-        .collect::<proc_macro2::TokenStream>();
+/// Generates a public struct for every table defined by the migrations, along with a `Table`
+/// enum listing them.
+///
+/// ```ignore
+/// mod schema {
+///     blackbird_macros::schema_module!("./examples/migrations");
+/// }
+///
+/// let p = schema::Person { name: None, username: "b0b".to_string() };
+/// assert_eq!(schema::Person::TABLE.name(), "person");
+/// ```
+#[proc_macro]
+pub fn schema_module(input: TokenStream) -> TokenStream {
+    let SchemaModule { tracking, items } = parse_macro_input!(input as SchemaModule);
 
-    let expanded = quote! {
-        struct #name {
-            #fields
+    TokenStream::from(quote! {
+        #tracking
+        #items
+    })
+}
+
+/// Checks a SurrealQL query against the schema produced by the migrations at compile time,
+/// and expands to a [`TypedQuery`](blackbird_core::query::TypedQuery).
+///
+/// When the query is a single `SELECT` from one table, rows are decoded into a generated `Row`
+/// struct with a field per selected column. Otherwise they're left as `Value`s.
+///
+/// ```ignore
+/// let rows = surql!("SELECT name FROM person WHERE username = $u", "./examples/migrations")
+///     .fetch(&ds, &sess, Some(vars))
+///     .await?;
+/// println!("{:?}", rows[0].name);
+/// ```
+#[proc_macro]
+pub fn surql(input: TokenStream) -> TokenStream {
+    let SurqlQuery {
+        query,
+        columns,
+        tracking,
+    } = parse_macro_input!(input as SurqlQuery);
+
+    let expanded = match columns {
+        Some(columns) => {
+            let row = Ident::new("Row", Span::call_site());
+            let row_types = match row_items(&row, &columns) {
+                Ok(tokens) => tokens,
+                Err(e) => return syn::Error::new(query.span(), e).to_compile_error().into(),
+            };
+
+            quote! {{
+                #tracking
+                #row_types
+
+                ::blackbird_core::query::TypedQuery::<#row>::new(#query)
+            }}
         }
+        None => quote! {{
+            #tracking
+
+            ::blackbird_core::query::TypedQuery::<::surrealdb::sql::Value>::new(#query)
+        }},
     };
 
     TokenStream::from(expanded)
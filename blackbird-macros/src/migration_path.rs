@@ -0,0 +1,259 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use proc_macro2::Span;
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Expr, ExprLit, Lit, LitStr, Token,
+};
+
+const DEFAULT_MIGRATION_PATH: &str = "./src/migrations";
+
+/// A migration directory passed to one of our macros, either as a string literal or as an
+/// `env!`/`concat!` expression we can evaluate at compile time.
+pub(crate) struct MigrationPath {
+    pub(crate) value: String,
+    pub(crate) span: Span,
+}
+
+impl Parse for MigrationPath {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let expr: Expr = input.parse()?;
+
+        Ok(Self {
+            value: eval_str_expr(&expr)?,
+            span: expr.span(),
+        })
+    }
+}
+
+fn eval_str_expr(expr: &Expr) -> syn::Result<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(s), ..
+        }) => Ok(s.value()),
+        Expr::Macro(m) if m.mac.path.is_ident("env") => {
+            let args = m
+                .mac
+                .parse_body_with(Punctuated::<LitStr, Token![,]>::parse_terminated)?;
+            let var = args
+                .first()
+                .ok_or_else(|| syn::Error::new(m.span(), "env! takes an environment variable"))?;
+
+            env::var(var.value()).map_err(|_| {
+                syn::Error::new(
+                    var.span(),
+                    format!("Environment variable {} is not set", var.value()),
+                )
+            })
+        }
+        Expr::Macro(m) if m.mac.path.is_ident("concat") => m
+            .mac
+            .parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)?
+            .iter()
+            .map(eval_str_expr)
+            .collect(),
+        Expr::Path(p) => Err(syn::Error::new(
+            p.span(),
+            "The value of a constant isn't available to macros. \
+             Use a string literal, env!, concat!, or set `migrations` under \
+             [package.metadata.blackbird] in Cargo.toml instead",
+        )),
+        e => Err(syn::Error::new(
+            e.span(),
+            "Expected a string literal, env! or concat!",
+        )),
+    }
+}
+
+/// Works out which directory to read migrations from.
+///
+/// When no path is passed to the macro, this falls back to the `migrations` key under
+/// `[package.metadata.blackbird]` in the invoking crate's Cargo.toml, and then to
+/// `./src/migrations`.
+pub(crate) fn migration_dir(path: Option<&MigrationPath>) -> syn::Result<PathBuf> {
+    let path = match path {
+        Some(p) => p.value.clone(),
+        None => metadata_migration_path()?.unwrap_or_else(|| DEFAULT_MIGRATION_PATH.to_string()),
+    };
+
+    Ok(resolve_migration_path(&path))
+}
+
+/// Relative paths are resolved against the manifest directory of the crate invoking the macro,
+/// rather than whatever directory rustc happens to be running in.
+fn resolve_migration_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    let resolved = match manifest_dir() {
+        Some(manifest_dir) => manifest_dir.join(path),
+        None => path.to_path_buf(),
+    };
+
+    fs::canonicalize(&resolved).unwrap_or(resolved)
+}
+
+fn manifest_dir() -> Option<PathBuf> {
+    env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from)
+}
+
+/// The root of the workspace the invoking crate is in, which is the closest directory above it
+/// with a Cargo.toml that has a `[workspace]` table, or the crate itself if it isn't in one.
+fn workspace_root(manifest_dir: &Path) -> PathBuf {
+    manifest_dir
+        .ancestors()
+        .find(|dir| {
+            fs::read_to_string(dir.join("Cargo.toml"))
+                .ok()
+                .and_then(|manifest| toml::from_str::<toml::Value>(&manifest).ok())
+                .map_or(false, |manifest| manifest.get("workspace").is_some())
+        })
+        .unwrap_or(manifest_dir)
+        .to_path_buf()
+}
+
+/// Where we keep schemas between builds, under cargo's target directory.
+///
+/// Cargo doesn't tell proc macros where that is, so unless `CARGO_TARGET_DIR` is set we look for
+/// the closest `target` directory above the invoking crate, which also covers workspaces. A
+/// relative `CARGO_TARGET_DIR` is taken from the workspace root, since the directory the macro
+/// runs in can't be relied on.
+pub(crate) fn schema_cache_dir() -> Option<PathBuf> {
+    let target_dir = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .map(|dir| match manifest_dir() {
+            Some(manifest_dir) if dir.is_relative() => workspace_root(&manifest_dir).join(dir),
+            _ => dir,
+        })
+        .or_else(|| {
+            let manifest_dir = manifest_dir()?;
+            let existing = manifest_dir
+                .ancestors()
+                .map(|dir| dir.join("target"))
+                .find(|dir| dir.is_dir());
+
+            Some(existing.unwrap_or_else(|| manifest_dir.join("target")))
+        })?;
+
+    Some(target_dir.join("blackbird"))
+}
+
+/// The `[package.metadata.blackbird]` table from the invoking crate's Cargo.toml, if it has one.
+pub(crate) fn blackbird_metadata() -> syn::Result<Option<toml::value::Table>> {
+    let manifest_path = match manifest_dir() {
+        Some(dir) => dir.join("Cargo.toml"),
+        None => return Ok(None),
+    };
+
+    let manifest = match fs::read_to_string(&manifest_path) {
+        Ok(m) => m,
+        Err(_) => return Ok(None),
+    };
+
+    let manifest: toml::Value = toml::from_str(&manifest).map_err(|e| {
+        syn::Error::new(
+            Span::call_site(),
+            format!("Could not parse {}: {e}", manifest_path.display()),
+        )
+    })?;
+
+    let metadata = manifest
+        .get("package")
+        .and_then(|p| p.get("metadata"))
+        .and_then(|m| m.get("blackbird"))
+        .and_then(|b| b.as_table())
+        .cloned();
+
+    Ok(metadata)
+}
+
+fn metadata_value(key: &str) -> syn::Result<Option<toml::Value>> {
+    Ok(blackbird_metadata()?.and_then(|mut m| m.remove(key)))
+}
+
+fn metadata_migration_path() -> syn::Result<Option<String>> {
+    match metadata_value("migrations")? {
+        None => Ok(None),
+        Some(toml::Value::String(s)) => Ok(Some(s)),
+        Some(v) => Err(syn::Error::new(
+            Span::call_site(),
+            format!("Expected package.metadata.blackbird.migrations to be a string, got {v}"),
+        )),
+    }
+}
+
+/// Type overrides from `[package.metadata.blackbird.overrides]`, as a map of table to field to
+/// type, e.g. `"person.email" = "crate::Email"`.
+pub(crate) fn metadata_type_overrides() -> syn::Result<BTreeMap<String, BTreeMap<String, String>>> {
+    let overrides = match metadata_value("overrides")? {
+        None => return Ok(BTreeMap::new()),
+        Some(toml::Value::Table(t)) => t,
+        Some(v) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                format!("Expected package.metadata.blackbird.overrides to be a table, got {v}"),
+            ))
+        }
+    };
+
+    let mut by_table: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for (key, typ) in overrides {
+        let (table, field) = key.split_once('.').ok_or_else(|| {
+            syn::Error::new(
+                Span::call_site(),
+                format!("Expected overrides to be keyed by table.field, got {key}"),
+            )
+        })?;
+
+        let typ = match typ {
+            toml::Value::String(s) => s,
+            v => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("Expected the override for {key} to be a string, got {v}"),
+                ))
+            }
+        };
+
+        by_table
+            .entry(table.to_string())
+            .or_default()
+            .insert(field.to_string(), typ);
+    }
+
+    Ok(by_table)
+}
+
+/// When building offline, the directory to read a snapshot written by `blackbird schema dump`
+/// from, instead of executing the migrations.
+///
+/// Offline mode is turned on by setting `BLACKBIRD_OFFLINE=true`, or `offline = true` under
+/// `[package.metadata.blackbird]`. The snapshot is read from the `snapshot` directory set there,
+/// or the migration directory if that isn't set.
+pub(crate) fn offline_snapshot_dir(migration_dir: &Path) -> syn::Result<Option<PathBuf>> {
+    let offline = match env::var("BLACKBIRD_OFFLINE") {
+        Ok(v) => v == "true" || v == "1",
+        Err(_) => matches!(metadata_value("offline")?, Some(toml::Value::Boolean(true))),
+    };
+
+    if !offline {
+        return Ok(None);
+    }
+
+    match metadata_value("snapshot")? {
+        None => Ok(Some(migration_dir.to_path_buf())),
+        Some(toml::Value::String(s)) => Ok(Some(resolve_migration_path(&s))),
+        Some(v) => Err(syn::Error::new(
+            Span::call_site(),
+            format!("Expected package.metadata.blackbird.snapshot to be a string, got {v}"),
+        )),
+    }
+}
@@ -0,0 +1,4 @@
+DEFINE TABLE account SCHEMAFULL;
+DEFINE FIELD email ON account TYPE string ASSERT $value != NONE;
+DEFINE FIELD name ON account TYPE string;
+DEFINE INDEX account_email ON account FIELDS email UNIQUE;
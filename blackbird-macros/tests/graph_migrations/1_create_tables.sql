@@ -0,0 +1,8 @@
+DEFINE TABLE person SCHEMAFULL;
+DEFINE FIELD name ON person TYPE string ASSERT $value != NONE;
+DEFINE TABLE post SCHEMAFULL;
+DEFINE FIELD title ON post TYPE string ASSERT $value != NONE;
+DEFINE TABLE likes SCHEMAFULL;
+DEFINE FIELD in ON likes TYPE record(person);
+DEFINE FIELD out ON likes TYPE record(post);
+DEFINE FIELD at ON likes TYPE datetime VALUE time::now();
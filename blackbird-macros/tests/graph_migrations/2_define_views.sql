@@ -0,0 +1 @@
+DEFINE TABLE post_likes AS SELECT count() AS total, out FROM likes GROUP BY out;
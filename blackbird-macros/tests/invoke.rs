@@ -1,13 +1,446 @@
-use blackbird_macros::struct_for;
+use std::collections::BTreeMap;
 
-struct_for!(Person, "./blackbird/examples/migrations");
+use blackbird_core::{
+    apply_migrations_to_in_mem_db,
+    convert::{FromValue, ToValue},
+    read_migrations, run_single_statement, Error,
+};
+use blackbird_macros::{schema_module, struct_for, surql};
+use surrealdb::sql::{parse, Id, Thing, Value};
+
+struct_for!(
+    #[derive(Debug, Clone, PartialEq)]
+    pub Person,
+    "../blackbird/examples/migrations",
+    fields {
+        #[doc = "How the person is shown to others"]
+        name,
+    }
+);
 
 #[test]
 fn test_can_use_struct() {
-    let p = Person {
+    let p = NewPerson {
         name: Some("john".to_string()),
         username: "big_j".to_string(),
     };
 
+    assert_eq!(p.clone(), p);
+    assert!(p.validate().is_ok());
     assert_eq!(p.name.unwrap(), "john");
 }
+
+#[test]
+fn test_struct_round_trips_through_value() -> Result<(), Error> {
+    let p = Person {
+        id: PersonId(Id::from("big_j")),
+        name: None,
+        username: "big_j".to_string(),
+    };
+
+    let value = p.to_value();
+    assert_eq!(value.to_string(), "{ id: person:big_j, username: 'big_j' }");
+
+    let p = Person::from_value(value)?;
+    assert_eq!(p.id, PersonId(Id::from("big_j")));
+    assert_eq!(p.name, None);
+    assert_eq!(p.username, "big_j");
+
+    let id = PersonId::from_value(Value::Thing(Thing::from(("person", "bob"))))?;
+    assert_eq!(id.to_value().to_string(), "person:bob");
+    assert!(PersonId::from_value(Value::Thing(Thing::from(("post", "bob")))).is_err());
+
+    Ok(())
+}
+
+mod from_metadata {
+    blackbird_macros::struct_for!(Person);
+
+    pub fn username() -> String {
+        let p = NewPerson {
+            name: None,
+            username: "big_j".to_string(),
+        };
+
+        p.username
+    }
+}
+
+mod from_env {
+    blackbird_macros::struct_for!(
+        Person,
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../blackbird/examples/migrations"
+        )
+    );
+
+    pub fn username() -> String {
+        let p = NewPerson {
+            name: None,
+            username: "big_j".to_string(),
+        };
+
+        p.username
+    }
+}
+
+#[test]
+fn test_can_use_struct_with_declared_path() {
+    assert_eq!(from_metadata::username(), from_env::username());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Username(String);
+
+impl FromValue for Username {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        String::from_value(value).map(Username)
+    }
+}
+
+impl ToValue for Username {
+    fn to_value(&self) -> Value {
+        self.0.to_value()
+    }
+}
+
+mod with_override {
+    blackbird_macros::struct_for!(
+        pub Person,
+        "../blackbird/examples/migrations",
+        overrides {
+            username: super::Username,
+        }
+    );
+}
+
+#[test]
+fn test_can_override_field_types() -> Result<(), Error> {
+    let p = with_override::Person {
+        id: with_override::PersonId(Id::from("big_j")),
+        name: None,
+        username: Username("big_j".to_string()),
+    };
+
+    let p = with_override::Person::from_value(p.to_value())?;
+    assert_eq!(p.username, Username("big_j".to_string()));
+
+    let username: blackbird_core::select::FieldRef<Username> = with_override::person::username;
+    assert_eq!(username.path(), &["username"]);
+
+    Ok(())
+}
+
+mod schema {
+    super::schema_module!("../blackbird/examples/migrations");
+}
+
+#[test]
+fn test_can_use_schema_module() {
+    let p = schema::NewPerson {
+        name: None,
+        username: "big_j".to_string(),
+    };
+
+    assert_eq!(p.username, "big_j");
+    assert_eq!(schema::Person::TABLE.name(), "person");
+    assert_eq!(schema::Table::ALL, [schema::Table::Person]);
+    assert_eq!(
+        schema::Table::from_name("person"),
+        Some(schema::Table::Person)
+    );
+}
+
+#[tokio::test]
+async fn test_can_insert_read_and_patch() -> Result<(), Error> {
+    let migrations = read_migrations("../blackbird/examples/migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let new = NewPerson {
+        name: Some("bob".to_string()),
+        username: "b0b".to_string(),
+    };
+    let inserted = run_single_statement(&ds, &sess, new.insert_statement(), None).await?;
+    let person = Vec::<Person>::from_value(inserted)?.remove(0);
+
+    assert_eq!(person.name.as_deref(), Some("bob"));
+
+    let patch = PersonPatch {
+        name: Some(None),
+        ..Default::default()
+    };
+    let updated = run_single_statement(&ds, &sess, patch.merge_statement(&person.id), None).await?;
+    let updated = Vec::<Person>::from_value(updated)?.remove(0);
+
+    assert_eq!(updated.id, person.id);
+    assert_eq!(updated.name, None);
+    assert_eq!(updated.username, "b0b");
+
+    Ok(())
+}
+
+mod graph {
+    super::schema_module!("tests/graph_migrations");
+}
+
+#[tokio::test]
+async fn test_can_relate_records() -> Result<(), Error> {
+    let migrations = read_migrations("tests/graph_migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let person = graph::NewPerson {
+        name: "bob".to_string(),
+    };
+    let inserted = run_single_statement(&ds, &sess, person.insert_statement(), None).await?;
+    let person = Vec::<graph::Person>::from_value(inserted)?.remove(0);
+
+    let post = graph::NewPost {
+        title: "Hello".to_string(),
+    };
+    let inserted = run_single_statement(&ds, &sess, post.insert_statement(), None).await?;
+    let post = Vec::<graph::Post>::from_value(inserted)?.remove(0);
+
+    let likes = graph::NewLikes {
+        r#in: person.id.clone(),
+        out: post.id.clone(),
+    };
+    let related = run_single_statement(&ds, &sess, likes.relate_statement(), None).await?;
+    let likes = Vec::<graph::Likes>::from_value(related)?.remove(0);
+
+    assert_eq!(likes.r#in, person.id);
+    assert_eq!(likes.out, post.id);
+
+    let liked = person.likes().posts(&ds, &sess).await?;
+    assert_eq!(liked.len(), 1);
+    assert_eq!(liked[0].id, post.id);
+    assert_eq!(liked[0].title, "Hello");
+
+    let edges = person.likes().edges(&ds, &sess).await?;
+    assert_eq!(edges[0].id, likes.id);
+
+    let select = parse("SELECT * FROM post_likes")?;
+    let counts = run_single_statement(&ds, &sess, select.0 .0[0].clone(), None).await?;
+    let counts = Vec::<graph::PostLikes>::from_value(counts)?;
+
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0].total, Some(1));
+    assert_eq!(counts[0].out, Some(post.id.thing()));
+
+    Ok(())
+}
+
+mod schemaless {
+    blackbird_macros::struct_for!(pub Event, "tests/schemaless_migrations");
+}
+
+#[tokio::test]
+async fn test_schemaless_keeps_undefined_fields() -> Result<(), Error> {
+    let migrations = read_migrations("tests/schemaless_migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let mut extra = BTreeMap::new();
+    extra.insert("source".to_string(), Value::from("web"));
+
+    let event = schemaless::NewEvent {
+        kind: "signup".to_string(),
+        payload: None,
+        extra,
+    };
+    let inserted = run_single_statement(&ds, &sess, event.insert_statement(), None).await?;
+    let event = Vec::<schemaless::Event>::from_value(inserted)?.remove(0);
+
+    assert_eq!(event.kind, "signup");
+    assert_eq!(event.extra.get("source"), Some(&Value::from("web")));
+    assert!(!event.extra.contains_key("kind"));
+    assert!(!event.extra.contains_key("id"));
+
+    assert!(event.to_value().to_string().contains("source: 'web'"));
+
+    Ok(())
+}
+
+mod nested {
+    blackbird_macros::struct_for!(pub Place, "tests/nested_migrations");
+}
+
+#[tokio::test]
+async fn test_can_read_fields_nested_in_objects() -> Result<(), Error> {
+    let migrations = read_migrations("tests/nested_migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let place = nested::NewPlace {
+        address: None,
+        address_city: "Wellington".to_string(),
+    };
+    let inserted = run_single_statement(&ds, &sess, place.insert_statement(), None).await?;
+    let place = Vec::<nested::Place>::from_value(inserted)?.remove(0);
+
+    assert_eq!(place.address_city, "Wellington");
+    let address = place
+        .address
+        .expect("address should be read along with its city");
+    assert_eq!(address.get("city"), Some(&Value::from("Wellington")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_can_build_typed_select() -> Result<(), Error> {
+    let migrations = read_migrations("../blackbird/examples/migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    for username in ["b0b", "al1ce"] {
+        let new = NewPerson {
+            name: Some(username.to_string()),
+            username: username.to_string(),
+        };
+        run_single_statement(&ds, &sess, new.insert_statement(), None).await?;
+    }
+
+    let select = Person::select()
+        .filter(person::username.eq("b0b"))
+        .order_by(person::name)
+        .limit(10);
+
+    assert_eq!(
+        select.sql(),
+        "SELECT * FROM person WHERE username = 'b0b' ORDER BY name ASC LIMIT 10"
+    );
+
+    let people = select.fetch(&ds, &sess).await?;
+    assert_eq!(people.len(), 1);
+    assert_eq!(people[0].username, "b0b");
+
+    let everyone = Person::select()
+        .order_by(person::username.desc())
+        .fetch(&ds, &sess)
+        .await?;
+    assert_eq!(everyone[0].username, "b0b");
+    assert_eq!(everyone[1].username, "al1ce");
+
+    Ok(())
+}
+
+#[test]
+fn test_can_use_checked_query() {
+    let query = surql!(
+        "SELECT name FROM person WHERE username = $u",
+        "../blackbird/examples/migrations"
+    );
+
+    assert_eq!(query.sql(), "SELECT name FROM person WHERE username = $u");
+}
+
+#[tokio::test]
+async fn test_can_fetch_typed_rows() -> Result<(), Error> {
+    let migrations = read_migrations("../blackbird/examples/migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let create = parse("CREATE person SET name = 'bob', username = 'b0b'")?;
+    run_single_statement(&ds, &sess, create.0 .0[0].clone(), None).await?;
+
+    let mut vars = BTreeMap::new();
+    vars.insert("u".to_string(), Value::Strand("b0b".into()));
+
+    let rows = surql!(
+        "SELECT name, username FROM person WHERE username = $u",
+        "../blackbird/examples/migrations"
+    )
+    .fetch(&ds, &sess, Some(vars))
+    .await?;
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].name.as_deref(), Some("bob"));
+    assert_eq!(rows[0].username, "b0b");
+
+    Ok(())
+}
+
+mod paging {
+    super::schema_module!("tests/paging_migrations");
+}
+
+#[tokio::test]
+async fn test_can_page_through_tables() -> Result<(), Error> {
+    let migrations = read_migrations("tests/paging_migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    for username in ["c4rl", "al1ce", "b0b"] {
+        let new = paging::NewMember {
+            username: username.to_string(),
+        };
+        run_single_statement(&ds, &sess, new.insert_statement(), None).await?;
+    }
+
+    let first = paging::Member::list(&ds, &sess, 0, 2).await?;
+    assert_eq!(first.items.len(), 2);
+    assert_eq!(first.next, Some(2));
+    let rest = paging::Member::list(&ds, &sess, 2, 2).await?;
+    assert_eq!(rest.items.len(), 1);
+    assert_eq!(rest.next, None);
+
+    let first = paging::Member::list_by_username(&ds, &sess, None, 2).await?;
+    let usernames: Vec<_> = first.items.iter().map(|m| m.username.as_str()).collect();
+    assert_eq!(usernames, ["al1ce", "b0b"]);
+    assert_eq!(first.next.as_deref(), Some("b0b"));
+    let rest = paging::Member::list_by_username(&ds, &sess, first.next, 2).await?;
+    assert_eq!(rest.items[0].username, "c4rl");
+    assert_eq!(rest.next, None);
+
+    assert!(matches!(
+        paging::Member::list(&ds, &sess, 0, 0).await,
+        Err(Error::EmptyPage)
+    ));
+
+    Ok(())
+}
+
+mod bulk {
+    super::schema_module!("tests/bulk_migrations");
+}
+
+#[tokio::test]
+async fn test_can_insert_and_upsert_in_bulk() -> Result<(), Error> {
+    let migrations = read_migrations("tests/bulk_migrations")?;
+    let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
+
+    let new = |email: &str, name: &str| bulk::NewAccount {
+        email: email.to_string(),
+        name: Some(name.to_string()),
+    };
+
+    let inserted = bulk::NewAccount::insert_many(
+        &ds,
+        &sess,
+        &[new("bob@example.com", "bob"), new("al@example.com", "al")],
+    )
+    .await?;
+    assert_eq!(inserted.len(), 2);
+    assert_eq!(inserted[0].email, "bob@example.com");
+    assert_eq!(inserted[1].email, "al@example.com");
+
+    let upserted = bulk::NewAccount::upsert_many_by_email(
+        &ds,
+        &sess,
+        &[
+            new("ann@example.com", "ann"),
+            new("bob@example.com", "robert"),
+        ],
+    )
+    .await?;
+    assert_eq!(upserted[0].name.as_deref(), Some("ann"));
+    assert_eq!(upserted[1].id, inserted[0].id);
+    assert_eq!(upserted[1].name.as_deref(), Some("robert"));
+
+    let al = bulk::Account {
+        id: inserted[1].id.clone(),
+        email: "al@example.com".to_string(),
+        name: Some("alan".to_string()),
+    };
+    let replaced = bulk::Account::upsert_many(&ds, &sess, &[al]).await?;
+    assert_eq!(replaced[0].id, inserted[1].id);
+    assert_eq!(replaced[0].name.as_deref(), Some("alan"));
+
+    Ok(())
+}
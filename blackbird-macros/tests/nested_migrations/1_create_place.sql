@@ -0,0 +1,3 @@
+DEFINE TABLE place SCHEMAFULL;
+DEFINE FIELD address ON place TYPE object;
+DEFINE FIELD address.city ON place TYPE string ASSERT $value != NONE;
@@ -0,0 +1,3 @@
+DEFINE TABLE member SCHEMAFULL;
+DEFINE FIELD username ON member TYPE string ASSERT $value != NONE;
+DEFINE INDEX member_username ON member FIELDS username UNIQUE;
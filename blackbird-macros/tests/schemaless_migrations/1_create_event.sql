@@ -0,0 +1,3 @@
+DEFINE TABLE event SCHEMALESS;
+DEFINE FIELD kind ON event TYPE string ASSERT $value != NONE;
+DEFINE FIELD payload ON event;
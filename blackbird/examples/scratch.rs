@@ -1,45 +1,21 @@
-use std::collections::BTreeMap;
-
-use blackbird_core::{apply_migrations_to_in_mem_db, read_migrations, run_single_statement, Error};
+use blackbird_core::{
+    apply_migrations_to_in_mem_db, convert::FromValue, read_migrations, run_single_statement, Error,
+};
 use blackbird_macros::struct_for;
-use surrealdb::sql::{statements::InsertStatement, Data, Object, Statement, Value};
-
-const MIGRATIONS_DIR: &str = "./blackbird/examples/migrations";
 
-struct_for!(Person, "./blackbird/examples/migrations");
-const TABLE_NAME: &str = "person";
+const MIGRATIONS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/migrations");
 
-impl From<&Person> for BTreeMap<String, Value> {
-    fn from(value: &Person) -> Self {
-        let mut output = BTreeMap::new();
-        if let Some(name) = value.name.as_ref() {
-            output.insert("name".to_string(), Value::Strand(name.as_str().into()));
-        }
-        output.insert(
-            "username".to_string(),
-            Value::Strand(value.username.as_str().into()),
-        );
-
-        output
-    }
-}
-
-impl Person {
-    fn insert_statement(&self) -> Statement {
-        Statement::Insert(InsertStatement {
-            into: TABLE_NAME.into(),
-            data: Data::SingleExpression(Value::Object(Object(self.into()))),
-            ..InsertStatement::default()
-        })
-    }
-}
+struct_for!(
+    #[derive(Debug)]
+    Person
+);
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let migrations = read_migrations(MIGRATIONS_DIR)?;
     let (ds, sess) = apply_migrations_to_in_mem_db(migrations).await?;
 
-    let p = Person {
+    let p = NewPerson {
         name: Some("bob".to_string()),
         username: "b0b".to_string(),
     };
@@ -63,5 +39,8 @@ async fn main() -> Result<(), Error> {
     //     .collect(),
     // ))]));
 
+    let people = Vec::<Person>::from_value(insert_val)?;
+    println!("people: {people:#?}");
+
     Ok(())
 }
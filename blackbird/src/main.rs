@@ -0,0 +1,137 @@
+use std::{env, path::PathBuf, process::ExitCode};
+
+use blackbird_core::{permissions::AccessReport, snapshot::Snapshot, Error};
+
+const USAGE: &str = "Usage:
+    blackbird schema dump [--migrations <dir>] [--out <dir>] [--check]
+    blackbird schema permissions [--migrations <dir>] [--json]
+
+Commands:
+    schema dump         Writes the schema produced by the migrations to schema.surql and
+                        schema.json, so the macros can use it with BLACKBIRD_OFFLINE=true
+    schema permissions  Lists who can select, create, update and delete each table and field
+
+Options:
+    --migrations   Directory containing the migrations [default: ./src/migrations]
+    --out          Directory to write the snapshot to [default: the migrations directory]
+    --check        Fail if the existing snapshot is out of date, rather than writing it
+    --json         Print the permissions as JSON";
+
+const DEFAULT_MIGRATION_PATH: &str = "./src/migrations";
+
+struct DumpArgs {
+    migrations: PathBuf,
+    out: Option<PathBuf>,
+    check: bool,
+}
+
+fn parse_dump_args(args: &[&str]) -> Result<DumpArgs, String> {
+    let mut dump_args = DumpArgs {
+        migrations: PathBuf::from(DEFAULT_MIGRATION_PATH),
+        out: None,
+        check: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--migrations" => {
+                dump_args.migrations = args.next().ok_or("--migrations needs a directory")?.into()
+            }
+            "--out" => dump_args.out = Some(args.next().ok_or("--out needs a directory")?.into()),
+            "--check" => dump_args.check = true,
+            a => return Err(format!("Unexpected argument {a}")),
+        }
+    }
+
+    Ok(dump_args)
+}
+
+struct PermissionsArgs {
+    migrations: PathBuf,
+    json: bool,
+}
+
+fn parse_permissions_args(args: &[&str]) -> Result<PermissionsArgs, String> {
+    let mut permissions_args = PermissionsArgs {
+        migrations: PathBuf::from(DEFAULT_MIGRATION_PATH),
+        json: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--migrations" => {
+                permissions_args.migrations =
+                    args.next().ok_or("--migrations needs a directory")?.into()
+            }
+            "--json" => permissions_args.json = true,
+            a => return Err(format!("Unexpected argument {a}")),
+        }
+    }
+
+    Ok(permissions_args)
+}
+
+async fn schema_permissions(args: PermissionsArgs) -> Result<(), Error> {
+    let snapshot = Snapshot::from_migrations(&args.migrations).await?;
+    let report = AccessReport::new(&snapshot.schemas);
+
+    if args.json {
+        println!("{}", report.to_json()?);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(())
+}
+
+async fn schema_dump(args: DumpArgs) -> Result<(), Error> {
+    let snapshot = Snapshot::from_migrations(&args.migrations).await?;
+    let out = args.out.unwrap_or(args.migrations);
+
+    if args.check {
+        snapshot.check(&out)?;
+        println!("Snapshot in {} is up to date", out.display());
+    } else {
+        snapshot.write(&out)?;
+        println!("Wrote snapshot to {}", out.display());
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match args.as_slice() {
+        ["schema", "dump", rest @ ..] => match parse_dump_args(rest) {
+            Ok(dump_args) => schema_dump(dump_args).await,
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        },
+        ["schema", "permissions", rest @ ..] => match parse_permissions_args(rest) {
+            Ok(permissions_args) => schema_permissions(permissions_args).await,
+            Err(e) => {
+                eprintln!("{e}\n\n{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}